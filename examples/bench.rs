@@ -0,0 +1,101 @@
+//! Times a few recursion-heavy programs, run with `cargo run --release --example bench`
+
+extern crate lisp_interpreter;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lisp_interpreter::{Interpreter, Backend};
+
+const SETUP: &str = "
+    (defun range (n acc)
+        (cond ((= n 0) acc)
+              ('t (range (- n 1) (cons n acc)))))
+
+    (defun count (n)
+        (cond ((= n 0) 'done)
+              ('t (count (- n 1)))))
+
+    (defun length (l)
+        (cond ((eq l '()) 0)
+              ('t (+ 1 (length (cdr l))))))
+
+    (defun curry (a)
+        (lambda (b)
+            (lambda (c)
+                (lambda (d)
+                    (label sum (lambda (n acc)
+                        (cond ((= n 0) acc)
+                              ('t (sum (- n 1) (+ acc a b c d))))))))))
+
+    (defun map (f l)
+        (cond ((eq l '()) '())
+              ('t (cons (f (car l)) (map f (cdr l))))))
+
+    (defun filter (f l)
+        (cond ((eq l '()) '())
+              ((f (car l)) (cons (car l) (filter f (cdr l))))
+              ('t (filter f (cdr l)))))
+
+    (defun reverse (l acc)
+        (cond ((eq l '()) acc)
+              ('t (reverse (cdr l) (cons (car l) acc)))))
+
+    (defun sum (l acc)
+        (cond ((eq l '()) acc)
+              ('t (sum (cdr l) (+ acc (car l))))))
+
+    (define deep (range 5000 '()))
+";
+
+const BENCHMARKS: &[(&str, &str)] = &[
+    ("deep label recursion", "((label walk (lambda (l) (cond ((eq l '()) 0) ('t (+ 1 (walk (cdr l))))))) deep)"),
+    ("deep defun recursion", "(length deep)"),
+    ("tail calls", "(count 100000)"),
+    ("nested closures", "(((((curry 1) 2) 3) 4) 100000 0)"),
+    ("list processing", "(sum (reverse (filter (lambda (x) (cond ((< x 2500) '()) ('t 't))) (map (lambda (x) (* x 3)) deep)) '()) 0)"),
+];
+
+const RUNS: u32 = 10;
+
+fn main()
+{
+    // the non-tail recursive benchmarks need more stack than the main thread has
+    let bench = thread::Builder::new()
+        .stack_size(1 << 30)
+        .spawn(run)
+        .expect("Could not start the benchmark thread");
+
+    bench.join().expect("The benchmark thread panicked");
+}
+
+fn run()
+{
+    for &backend in [Backend::TreeWalker, Backend::Bytecode].iter()
+    {
+        println!("{:?}", backend);
+
+        bench(Interpreter::with_backend(backend));
+    }
+}
+
+fn bench(mut interpreter: Interpreter)
+{
+    interpreter.eval_str(SETUP).expect("The benchmark setup failed");
+
+    for &(name, program) in BENCHMARKS.iter()
+    {
+        let mut total = Duration::new(0, 0);
+
+        for _ in 0..RUNS
+        {
+            let start = Instant::now();
+
+            interpreter.eval_str(program).expect("The benchmark failed");
+
+            total += start.elapsed();
+        }
+
+        println!("    {:<24} {:>10.3} ms", name, total.as_secs_f64() * 1000.0 / f64::from(RUNS));
+    }
+}
@@ -0,0 +1,1119 @@
+//! Resolves expressions into a tree of `Node`s before they're evaluated
+//!
+//! Every variable is resolved to a slot in one of the enclosing closures' frames, or else to a
+//! global, so neither backend looks names up at runtime, and unbound names can be found before
+//! anything runs
+
+use std::rc::Rc;
+use std::cell::OnceCell;
+
+use expression::*;
+use error::EvalError;
+use eval::Environment;
+use vm::Code;
+use syntax::strip;
+
+/// Where a local variable lives: `depth` frames out from the innermost one, at `index` in that frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Address
+{
+    pub depth: usize,
+    pub index: usize,
+}
+
+#[derive(Clone)]
+pub struct Node(Rc<NodeInner>);
+
+struct NodeInner
+{
+    kind: NodeKind,
+    form: Expression,
+}
+
+pub enum NodeKind
+{
+    Constant(Expression),
+    Local(Atom, Address),
+    Global(Atom),
+    /// The clauses in order, each with a test and a body unless it gives the test's value, and the
+    /// error for a malformed clause after them
+    Cond(Vec<(Node, Option<Node>)>, Option<EvalError>),
+    /// A test, and the branches for when it's true and when it isn't
+    If(Node, Node, Option<Node>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    /// Evaluates each node in order, for the value of the last one
+    Progn(Vec<Node>),
+    /// Builds a pair, for a quasiquote
+    Cons(Node, Node),
+    /// Builds a copy of the list the first node gives, ending in the value of the second, for an unquote-splicing
+    Append(Node, Node),
+    Lambda(Rc<Lambda>),
+    Label(Atom, Node),
+    Define(Atom, Node),
+    /// Sets the macro expander for a name to a closure of the lambda
+    Defmacro(Atom, Rc<Lambda>),
+    /// Expands the form the node gives, either once or until it's no longer a call to a macro
+    Macroexpand(Node, bool),
+    /// Evaluates the values, then the body in a new frame holding them
+    Let(Vec<Node>, Node),
+    /// Like `Let`, but the values are evaluated in the new frame, filling it's slots in order
+    Letrec(Vec<Node>, Node),
+    /// A call to a function, with it's arguments; macros and special forms use the arguments as written instead
+    Call(Node, Vec<Node>),
+    /// Fills the slots of optional and keyword parameters that weren't passed with their defaults, in
+    /// order, before the body of a lambda
+    Defaults(Vec<(usize, Node)>, Node),
+    /// A malformed form, which fails when it's evaluated
+    Error(EvalError),
+}
+
+impl Node
+{
+    fn new(kind: NodeKind, form: &Expression) -> Node
+    {
+        Node(Rc::new(NodeInner
+        {
+            kind,
+            form: form.clone(),
+        }))
+    }
+
+    pub fn kind(&self) -> &NodeKind
+    {
+        &self.0.kind
+    }
+
+    /// The expression this node was made from, which errors while evaluating it point to
+    pub fn form(&self) -> &Expression
+    {
+        &self.0.form
+    }
+
+    /// The arguments of a call, as written
+    pub fn raw_arguments(&self) -> ListIter<'_>
+    {
+        match self.0.form
+        {
+            Expression::List(ref list) =>
+            {
+                let mut arguments = list.iter();
+                arguments.next();
+                arguments
+            },
+            _ => unreachable!("only calls have arguments")
+        }
+    }
+}
+
+pub struct Lambda
+{
+    pub kind: ClosureKind,
+    /// Set when the lambda is written directly in a `label`, which binds the name in the lambda's own frame
+    pub label: Option<Atom>,
+    pub params: Parameters,
+    pub body: Node,
+    pub source: Expression,
+    /// The body compiled for the bytecode backend, the first time it's called there
+    pub code: OnceCell<Rc<Code>>,
+}
+
+impl Lambda
+{
+    /// The size of the frame for a call: the closure itself if it's labelled, then it's parameters
+    pub fn slots(&self) -> usize
+    {
+        self.label.iter().count() + self.params.names.len()
+    }
+}
+
+/// A lambda's parameters
+pub struct Parameters
+{
+    /// Every parameter, in the order of their slots: the required ones, those after `&optional`,
+    /// the one after `&rest` or the dot in a dotted list, and those after `&key`
+    pub names: Vec<Atom>,
+    pub required: usize,
+    pub optional: usize,
+    pub rest: bool,
+    /// The keyword each of the `keys` is passed with, interned once here so a call only compares symbols
+    pub keywords: Vec<Symbol>,
+}
+
+impl Parameters
+{
+    /// The names passed as `:name value` pairs after the other arguments
+    pub fn keys(&self) -> &[Atom]
+    {
+        &self.names[self.required + self.optional + self.rest as usize..]
+    }
+
+    pub fn arity(&self) -> Arity
+    {
+        match (self.rest || !self.keys().is_empty(), self.optional)
+        {
+            (true, _) => Arity::AtLeast(self.required),
+            (false, 0) => Arity::Exact(self.required),
+            (false, optional) => Arity::Between(self.required, self.required + optional)
+        }
+    }
+}
+
+pub fn analyze(expr: &Expression) -> Node
+{
+    Analyzer::new(None).expression(expr)
+}
+
+/// Finds the variables in `exprs` that would be unbound when they're evaluated: those that aren't
+/// locals, globals in `env`, or defined by one of `exprs`
+///
+/// Only the bodies of lambdas and macros are checked, since the rest is evaluated straight away and
+/// fails then anyway. The arguments of calls to macros and special forms aren't checked, since
+/// they aren't evaluated
+pub fn unbound(exprs: &[Expression], env: &Environment) -> Vec<EvalError>
+{
+    let mut analyzer = Analyzer::new(Some(env));
+
+    for expr in exprs
+    {
+        analyzer.expression(expr);
+    }
+
+    let Analyzer { free, defined, .. } = analyzer;
+
+    free.into_iter()
+        .filter(|atom| !defined.contains(&atom.symbol()) && env.global(atom.symbol()).is_none())
+        .map(|atom| EvalError::unbound(atom).in_form(&Expression::Atom(atom)))
+        .collect()
+}
+
+/// Splits the arguments of a special form that takes exactly `N` of them
+fn exactly<'a, const N: usize>(name: &str, arguments: ListIter<'a>) -> Result<[&'a Expression; N], EvalError>
+{
+    let mut found = [None; N];
+    let mut count = 0;
+
+    for arg in arguments
+    {
+        if count < N
+        {
+            found[count] = Some(arg);
+        }
+
+        count += 1;
+    }
+
+    if count != N
+    {
+        return Err(EvalError::arity(name, Arity::Exact(N), count));
+    }
+
+    Ok(found.map(|arg| arg.expect("every argument was counted")))
+}
+
+/// The value of an atom that always evaluates to itself: `t`, keywords like `:key`, and `nil`, which is `()`
+fn constant(atom: Atom) -> Option<Expression>
+{
+    match atom.symbol()
+    {
+        Symbol::NIL => Some(Expression::List(List::nil())),
+        Symbol::T => Some(Expression::Atom(atom)),
+        _ if atom.is_keyword() => Some(Expression::Atom(atom)),
+        _ => None
+    }
+}
+
+/// Splits a `(quasiquote x)`, `(unquote x)` or `(unquote-splicing x)` into the symbol and `x`
+pub fn wrapper(list: &List) -> Option<(Symbol, &Expression)>
+{
+    let symbol = match list.car()
+    {
+        Some(&Expression::Atom(atom)) => atom.symbol().root(),
+        _ => return None
+    };
+
+    if !matches!(symbol, Symbol::QUASIQUOTE | Symbol::UNQUOTE | Symbol::UNQUOTE_SPLICING)
+    {
+        return None;
+    }
+
+    let mut parts = list.iter();
+    parts.next();
+
+    match (parts.next(), parts.next())
+    {
+        (Some(inner), None) if list.is_proper() => Some((symbol, inner)),
+        _ => None
+    }
+}
+
+/// A node building a pair, which is a constant if both halves are
+fn pair(car: Node, cdr: Node, form: &Expression) -> Node
+{
+    if let (NodeKind::Constant(car), NodeKind::Constant(cdr)) = (car.kind(), cdr.kind())
+    {
+        return Node::new(NodeKind::Constant(Expression::List(List::cons(car.clone(), cdr.clone()))), form);
+    }
+
+    Node::new(NodeKind::Cons(car, cdr), form)
+}
+
+/// Checks that a name a form is about to bind isn't one of the constants
+fn bindable(what: &str, name: Atom) -> Result<Atom, EvalError>
+{
+    match constant(name)
+    {
+        Some(_) => Err(EvalError::type_error(what, "a name that isn't a constant", Expression::Atom(name))),
+        None => Ok(name)
+    }
+}
+
+/// Splits the arguments of a special form that takes `N` arguments followed by a body of at least one expression
+fn with_body<'a, const N: usize>(name: &str, mut arguments: ListIter<'a>) -> Result<([&'a Expression; N], ListIter<'a>), EvalError>
+{
+    let count = arguments.clone().count();
+
+    if count <= N
+    {
+        return Err(EvalError::arity(name, Arity::AtLeast(N + 1), count));
+    }
+
+    let found = [(); N].map(|_| arguments.next().expect("every argument was counted"));
+
+    Ok((found, arguments))
+}
+
+/// The optional and keyword parameters of a lambda, by their index among it's parameters, with their defaults
+type Defaults<'a> = Vec<(usize, Option<&'a Expression>)>;
+
+fn parameters(kind: ClosureKind, params: &Expression) -> Result<(Parameters, Defaults<'_>), EvalError>
+{
+    let what = match kind
+    {
+        ClosureKind::Lambda => "lambda",
+        ClosureKind::Macro => "macro"
+    };
+
+    // a lone atom takes every argument, like the tail of a dotted list
+    let (items, tail) = match *params
+    {
+        Expression::List(ref args) => (args.iter().collect::<Vec<_>>(), args.tail()),
+        Expression::Atom(_) => (Vec::new(), Some(params)),
+        ref other => return Err(EvalError::type_error(what, "a list of arguments", other.clone()))
+    };
+
+    let mut parameters = Parameters
+    {
+        names: Vec::new(),
+        required: 0,
+        optional: 0,
+        rest: false,
+        keywords: Vec::new(),
+    };
+
+    let mut defaults = Vec::new();
+    let mut section = Symbol::LAMBDA;
+
+    for item in items
+    {
+        if let Expression::Atom(atom) = *item
+        {
+            let marker = atom.symbol().root();
+
+            if matches!(marker, Symbol::OPTIONAL | Symbol::REST | Symbol::KEY)
+            {
+                let allowed = match marker
+                {
+                    Symbol::OPTIONAL => section == Symbol::LAMBDA,
+                    Symbol::REST => section == Symbol::LAMBDA || section == Symbol::OPTIONAL,
+                    _ => section != Symbol::KEY && (section != Symbol::REST || parameters.rest)
+                };
+
+                if !allowed
+                {
+                    return Err(EvalError::syntax(format!("{} is out of place in the argument list of a {}", atom, what)));
+                }
+
+                section = marker;
+                continue;
+            }
+        }
+
+        let (name, default) = match (section, item)
+        {
+            (_, &Expression::Atom(name)) => (name, None),
+            (Symbol::OPTIONAL, &Expression::List(ref spec)) | (Symbol::KEY, &Expression::List(ref spec)) =>
+            {
+                let parts = spec.iter().collect::<Vec<_>>();
+
+                match (spec.is_proper(), parts.as_slice())
+                {
+                    (true, [Expression::Atom(name)]) => (*name, None),
+                    (true, [Expression::Atom(name), default]) => (*name, Some(*default)),
+                    _ => return Err(EvalError::type_error(what, "a name or a (name default) list for each optional argument", item.clone()))
+                }
+            },
+            _ => return Err(EvalError::type_error(what, "only atoms in it's argument list", item.clone()))
+        };
+
+        match section
+        {
+            Symbol::LAMBDA => parameters.required += 1,
+            Symbol::OPTIONAL => parameters.optional += 1,
+            Symbol::REST if parameters.rest => return Err(EvalError::syntax(format!("&rest takes one name in the argument list of a {}", what))),
+            Symbol::REST => parameters.rest = true,
+            _ => {}
+        }
+
+        if section == Symbol::OPTIONAL || section == Symbol::KEY
+        {
+            defaults.push((parameters.names.len(), default));
+        }
+
+        if section == Symbol::KEY
+        {
+            parameters.keywords.push(Symbol::intern(&format!(":{}", name)));
+        }
+
+        parameters.names.push(bindable(what, name)?);
+    }
+
+    if section == Symbol::REST && !parameters.rest
+    {
+        return Err(EvalError::syntax(format!("&rest needs a name after it in the argument list of a {}", what)));
+    }
+
+    if let Some(tail) = tail
+    {
+        match *tail
+        {
+            Expression::Atom(name) if section == Symbol::LAMBDA || section == Symbol::OPTIONAL =>
+            {
+                parameters.rest = true;
+                parameters.names.push(bindable(what, name)?);
+            },
+            ref other => return Err(EvalError::type_error(what, "a name after the dot in it's argument list, and no &rest or &key", other.clone()))
+        }
+    }
+
+    Ok((parameters, defaults))
+}
+
+/// Splits the bindings of a `let` like form, `((name value) ...)`, into the names and the values
+fn split_bindings<'a>(what: &str, bindings: &'a Expression) -> Result<(Vec<Atom>, Vec<&'a Expression>), EvalError>
+{
+    let list = match *bindings
+    {
+        Expression::List(ref list) if list.is_proper() => list,
+        ref other => return Err(EvalError::type_error(what, "a list of bindings", other.clone()))
+    };
+
+    let mut names = Vec::new();
+    let mut values = Vec::new();
+
+    for binding in list.iter()
+    {
+        let pair = match *binding
+        {
+            Expression::List(ref pair) if pair.is_proper() => pair,
+            ref other => return Err(EvalError::type_error(what, "bindings like (name value)", other.clone()))
+        };
+
+        let mut parts = pair.iter();
+
+        match (parts.next(), parts.next(), parts.next())
+        {
+            (Some(&Expression::Atom(name)), Some(value), None) =>
+            {
+                names.push(bindable(what, name)?);
+                values.push(value);
+            },
+            _ => return Err(EvalError::type_error(what, "bindings like (name value)", binding.clone()))
+        }
+    }
+
+    Ok((names, values))
+}
+
+struct Analyzer<'a>
+{
+    /// The names of the slots in each enclosing frame, innermost last
+    frames: Vec<Vec<Symbol>>,
+    env: Option<&'a Environment>,
+    /// Global references inside the body of a lambda or macro, while `quiet` is zero
+    free: Vec<Atom>,
+    defined: Vec<Symbol>,
+    /// Names given to macros by a `defmacro`, in case one is used before it's evaluated
+    macros: Vec<Symbol>,
+    quiet: usize,
+    /// How many lambda and macro bodies the expression is in
+    lambdas: usize,
+}
+
+impl<'a> Analyzer<'a>
+{
+    fn new(env: Option<&'a Environment>) -> Analyzer<'a>
+    {
+        Analyzer
+        {
+            frames: Vec::new(),
+            env,
+            free: Vec::new(),
+            defined: Vec::new(),
+            macros: Vec::new(),
+            quiet: 0,
+            lambdas: 0,
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Node
+    {
+        match *expr
+        {
+            Expression::Atom(atom) => Node::new(self.resolve(atom), expr),
+            Expression::List(ref list) => self.form(list, expr),
+            ref value => Node::new(NodeKind::Constant(value.clone()), expr)
+        }
+    }
+
+    fn resolve(&mut self, atom: Atom) -> NodeKind
+    {
+        if let Some(value) = constant(atom)
+        {
+            return NodeKind::Constant(value);
+        }
+
+        for (depth, frame) in self.frames.iter().rev().enumerate()
+        {
+            // later slots shadow earlier ones, like a repeated parameter
+            if let Some(index) = frame.iter().rposition(|&name| name == atom.symbol())
+            {
+                return NodeKind::Local(atom, Address
+                {
+                    depth,
+                    index,
+                });
+            }
+        }
+
+        // an identifier a hygienic macro introduced, that the expansion didn't bind, means what it's
+        // original does where the macro was defined, which is at the top level unless the macro was
+        // itself introduced by an expansion
+        match atom.symbol().original()
+        {
+            Some(original) if original.original().is_some() => self.resolve(atom.renamed(original)),
+            Some(original) => self.global(atom.renamed(original)),
+            None => self.global(atom)
+        }
+    }
+
+    fn global(&mut self, atom: Atom) -> NodeKind
+    {
+        if let Some(value) = constant(atom)
+        {
+            return NodeKind::Constant(value);
+        }
+
+        if self.quiet == 0 && self.lambdas > 0
+        {
+            self.free.push(atom);
+        }
+
+        NodeKind::Global(atom)
+    }
+
+    fn form(&mut self, list: &List, expr: &Expression) -> Node
+    {
+        let result = match list.car()
+        {
+            // a quasiquote can analyse to any kind of node, like the expression it unquotes
+            Some(&Expression::Atom(atom)) if atom.symbol().root() == Symbol::QUASIQUOTE && list.is_proper() =>
+            {
+                let mut arguments = list.iter();
+                arguments.next();
+
+                exactly("quasiquote", arguments).and_then(|[template]| self.template(template, 1, expr))
+            },
+            _ => self.special_form(list).map(|kind| Node::new(kind, expr))
+        };
+
+        match result
+        {
+            Ok(node) => node,
+            Err(err) => Node::new(NodeKind::Error(err), expr)
+        }
+    }
+
+    /// Analyses a quasiquote template nested `depth` quasiquotes deep, where only unquotes at depth 1 are evaluated
+    fn template(&mut self, expr: &Expression, depth: usize, form: &Expression) -> Result<Node, EvalError>
+    {
+        let list = match *expr
+        {
+            Expression::List(ref list) if !list.is_empty() => list,
+            ref value => return Ok(Node::new(NodeKind::Constant(strip(value)), form))
+        };
+
+        if let Some((symbol, inner)) = wrapper(list)
+        {
+            let depth = match symbol
+            {
+                Symbol::UNQUOTE if depth == 1 => return Ok(self.expression(inner)),
+                Symbol::UNQUOTE_SPLICING if depth == 1 => return Err(EvalError::syntax("unquote-splicing can only be used inside a list")),
+                Symbol::QUASIQUOTE => depth + 1,
+                _ => depth - 1
+            };
+
+            // rebuild the form around it's argument, which can itself be spliced in
+            let name = Node::new(NodeKind::Constant(Expression::Atom(Atom::from(symbol))), form);
+
+            return match list.cdr()
+            {
+                Some(Expression::List(rest)) => Ok(pair(name, self.elements(rest, depth, form)?, form)),
+                _ => unreachable!("the form has one argument")
+            };
+        }
+
+        self.elements(list, depth, form)
+    }
+
+    /// Analyses the elements of a list in a quasiquote template, splicing in those that are unquote-splicings at depth 1
+    fn elements(&mut self, list: &List, depth: usize, form: &Expression) -> Result<Node, EvalError>
+    {
+        let mut items = Vec::new();
+        let mut rest = list;
+
+        let tail = loop
+        {
+            if rest.is_empty()
+            {
+                break Node::new(NodeKind::Constant(Expression::List(List::nil())), form);
+            }
+
+            // the tail of `(a . ,b)`, which reads as `(a unquote b)`
+            if !items.is_empty() && wrapper(rest).is_some()
+            {
+                break self.template(&Expression::List(rest.clone()), depth, form)?;
+            }
+
+            let item = rest.car().expect("the list isn't empty");
+
+            items.push(match *item
+            {
+                Expression::List(ref list) => match wrapper(list)
+                {
+                    Some((Symbol::UNQUOTE_SPLICING, inner)) if depth == 1 => (true, self.expression(inner)),
+                    _ => (false, self.template(item, depth, form)?)
+                },
+                _ => (false, self.template(item, depth, form)?)
+            });
+
+            match rest.cdr()
+            {
+                Some(Expression::List(next)) => rest = next,
+                Some(tail) => break Node::new(NodeKind::Constant(strip(tail)), form),
+                None => unreachable!("the list isn't empty")
+            }
+        };
+
+        Ok(items.into_iter().rev().fold(tail, |rest, (spliced, item)| match spliced
+        {
+            true => Node::new(NodeKind::Append(item, rest), form),
+            false => pair(item, rest, form)
+        }))
+    }
+
+    fn special_form(&mut self, list: &List) -> Result<NodeKind, EvalError>
+    {
+        let mut arguments = list.iter();
+
+        let first = match arguments.next()
+        {
+            Some(first) => first,
+            None => return Ok(NodeKind::Constant(Expression::List(List::nil())))
+        };
+
+        if !list.is_proper()
+        {
+            return Err(EvalError::syntax("cannot evaluate an improper list"));
+        }
+
+        let symbol = match *first
+        {
+            Expression::Atom(atom) => atom.symbol().root(),
+            _ => return Ok(self.call(first, arguments))
+        };
+
+        let form = Expression::List(list.clone());
+
+        match symbol
+        {
+            Symbol::QUOTE =>
+            {
+                let [value] = exactly("quote", arguments)?;
+
+                Ok(NodeKind::Constant(strip(value)))
+            },
+            Symbol::UNQUOTE | Symbol::UNQUOTE_SPLICING => Err(EvalError::syntax(format!("{} can only be used inside a quasiquote", symbol.as_str()))),
+            Symbol::COND => Ok(self.cond(arguments)),
+            Symbol::IF =>
+            {
+                let count = arguments.clone().count();
+
+                if !Arity::Between(2, 3).accepts(count)
+                {
+                    return Err(EvalError::arity("if", Arity::Between(2, 3), count));
+                }
+
+                let mut parts = arguments.map(|part| self.expression(part));
+
+                let test = parts.next().expect("there's a test");
+                let then = parts.next().expect("there's a branch for when it's true");
+
+                Ok(NodeKind::If(test, then, parts.next()))
+            },
+            Symbol::WHEN =>
+            {
+                let ([test], body) = with_body("when", arguments)?;
+
+                Ok(NodeKind::If(self.expression(test), self.body(body, &form), None))
+            },
+            Symbol::UNLESS =>
+            {
+                let ([test], body) = with_body("unless", arguments)?;
+
+                let nil = Node::new(NodeKind::Constant(Expression::List(List::nil())), &form);
+
+                Ok(NodeKind::If(self.expression(test), nil, Some(self.body(body, &form))))
+            },
+            Symbol::AND => Ok(NodeKind::And(arguments.map(|arg| self.expression(arg)).collect())),
+            Symbol::OR => Ok(NodeKind::Or(arguments.map(|arg| self.expression(arg)).collect())),
+            Symbol::PROGN | Symbol::BEGIN => Ok(NodeKind::Progn(arguments.map(|arg| self.expression(arg)).collect())),
+            Symbol::LAMBDA =>
+            {
+                let ([params], body) = with_body("lambda", arguments)?;
+
+                Ok(NodeKind::Lambda(self.lambda(ClosureKind::Lambda, None, params, body, &form)?))
+            },
+            Symbol::MACRO =>
+            {
+                let ([params], body) = with_body("macro", arguments)?;
+
+                Ok(NodeKind::Lambda(self.lambda(ClosureKind::Macro, None, params, body, &form)?))
+            },
+            Symbol::LABEL =>
+            {
+                let [name, value] = exactly("label", arguments)?;
+
+                match *name
+                {
+                    Expression::Atom(name) =>
+                    {
+                        let name = bindable("label", name)?;
+                        Ok(NodeKind::Label(name, self.labelled(name, value)))
+                    },
+                    ref other => Err(EvalError::type_error("label", "an atom as it's first argument", other.clone()))
+                }
+            },
+            Symbol::DEFINE =>
+            {
+                let [name, value] = exactly("define", arguments)?;
+
+                match *name
+                {
+                    Expression::Atom(name) =>
+                    {
+                        let name = bindable("define", name.root())?;
+                        self.defined.push(name.symbol());
+                        Ok(NodeKind::Define(name, self.expression(value)))
+                    },
+                    ref other => Err(EvalError::type_error("define", "an atom as it's first argument", other.clone()))
+                }
+            },
+            Symbol::DEFUN =>
+            {
+                let ([name, params], body) = with_body("defun", arguments)?;
+
+                match *name
+                {
+                    Expression::Atom(name) =>
+                    {
+                        let name = bindable("defun", name.root())?;
+                        self.defined.push(name.symbol());
+
+                        let lambda = self.lambda(ClosureKind::Lambda, None, params, body, &form)?;
+
+                        Ok(NodeKind::Define(name, Node::new(NodeKind::Lambda(lambda), &form)))
+                    },
+                    ref other => Err(EvalError::type_error("defun", "an atom as it's first argument", other.clone()))
+                }
+            },
+            Symbol::DEFMACRO =>
+            {
+                let ([name, params], body) = with_body("defmacro", arguments)?;
+
+                match *name
+                {
+                    Expression::Atom(name) =>
+                    {
+                        let name = bindable("defmacro", name.root())?;
+                        self.macros.push(name.symbol());
+
+                        Ok(NodeKind::Defmacro(name, self.lambda(ClosureKind::Lambda, None, params, body, &form)?))
+                    },
+                    ref other => Err(EvalError::type_error("defmacro", "an atom as it's first argument", other.clone()))
+                }
+            },
+            Symbol::MACROEXPAND_1 | Symbol::MACROEXPAND =>
+            {
+                let [value] = exactly(symbol.as_str(), arguments)?;
+
+                Ok(NodeKind::Macroexpand(self.expression(value), symbol == Symbol::MACROEXPAND))
+            },
+            // the rules are read when the form is expanded, which is all it does
+            Symbol::DEFINE_SYNTAX =>
+            {
+                let [name, _] = exactly("define-syntax", arguments)?;
+
+                match *name
+                {
+                    Expression::Atom(name) =>
+                    {
+                        let name = bindable("define-syntax", name.root())?;
+                        self.macros.push(name.symbol());
+
+                        Ok(NodeKind::Constant(Expression::Atom(name)))
+                    },
+                    ref other => Err(EvalError::type_error("define-syntax", "an atom as it's first argument", other.clone()))
+                }
+            },
+            Symbol::LET =>
+            {
+                let ([bindings], body) = with_body("let", arguments)?;
+                let (names, values) = split_bindings("let", bindings)?;
+
+                let values = values.into_iter().map(|value| self.expression(value)).collect();
+
+                Ok(NodeKind::Let(values, self.scoped(names, body, &form)))
+            },
+            Symbol::LET_STAR =>
+            {
+                let ([bindings], body) = with_body("let*", arguments)?;
+                let (names, values) = split_bindings("let*", bindings)?;
+
+                if names.is_empty()
+                {
+                    return Ok(NodeKind::Let(Vec::new(), self.scoped(names, body, &form)));
+                }
+
+                // each binding gets it's own frame, so it can see the ones before it
+                let mut values = values.into_iter()
+                    .zip(names.iter())
+                    .map(|(value, name)|
+                    {
+                        let value = self.expression(value);
+                        self.frames.push(vec![name.symbol()]);
+                        value
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut body = self.body(body, &form);
+
+                for _ in 0..names.len()
+                {
+                    self.frames.pop();
+                }
+
+                while values.len() > 1
+                {
+                    let value = values.pop().expect("there's more than one value");
+                    body = Node::new(NodeKind::Let(vec![value], body), &form);
+                }
+
+                Ok(NodeKind::Let(values, body))
+            },
+            Symbol::LETREC =>
+            {
+                let ([bindings], body) = with_body("letrec", arguments)?;
+                let (names, values) = split_bindings("letrec", bindings)?;
+
+                self.frames.push(names.iter().map(|name| name.symbol()).collect());
+
+                let values = values.into_iter().map(|value| self.expression(value)).collect();
+                let body = self.body(body, &form);
+
+                self.frames.pop();
+
+                Ok(NodeKind::Letrec(values, body))
+            },
+            _ => Ok(self.call(first, arguments))
+        }
+    }
+
+    /// Analyses the expressions of a body, which are evaluated in order for the value of the last one
+    fn body(&mut self, exprs: ListIter, form: &Expression) -> Node
+    {
+        let mut nodes = exprs.map(|expr| self.expression(expr)).collect::<Vec<_>>();
+
+        if nodes.len() == 1
+        {
+            nodes.pop().expect("there's one node")
+        }
+        else
+        {
+            Node::new(NodeKind::Progn(nodes), form)
+        }
+    }
+
+    /// Analyses `body` in a new frame with a slot for each of `names`
+    fn scoped(&mut self, names: Vec<Atom>, body: ListIter, form: &Expression) -> Node
+    {
+        self.frames.push(names.iter().map(|name| name.symbol()).collect());
+
+        let body = self.body(body, form);
+
+        self.frames.pop();
+
+        body
+    }
+
+    fn cond(&mut self, arguments: ListIter) -> NodeKind
+    {
+        let mut clauses = Vec::new();
+
+        for clause in arguments
+        {
+            // a malformed clause is only an error if none of the clauses before it are true
+            let mut parts = match *clause
+            {
+                Expression::List(ref parts) if parts.is_proper() && !parts.is_empty() => parts.iter(),
+                _ => return NodeKind::Cond(clauses, Some(EvalError::syntax("each clause in a cond should be a list starting with a test")))
+            };
+
+            let test = self.expression(parts.next().expect("the clause isn't empty"));
+
+            // a clause with only a test gives the test's value
+            let body = if parts.clone().next().is_some() { Some(self.body(parts, clause)) } else { None };
+
+            clauses.push((test, body));
+        }
+
+        NodeKind::Cond(clauses, None)
+    }
+
+    fn lambda(&mut self, kind: ClosureKind, label: Option<Atom>, params: &Expression, body: ListIter, form: &Expression) -> Result<Rc<Lambda>, EvalError>
+    {
+        let (params, defaults) = parameters(kind, params)?;
+
+        let source = match body.clone().count()
+        {
+            1 => body.clone().next().expect("there's one expression").clone(),
+            _ => Expression::List(List::new(Some(Expression::Atom(Atom::from(Symbol::PROGN))).into_iter().chain(body.clone().cloned()).collect()))
+        };
+
+        self.frames.push(label.iter().chain(params.names.iter()).map(|atom| atom.symbol()).collect());
+        self.lambdas += 1;
+
+        // a default can use the parameters before it
+        let offset = label.iter().count();
+        let defaults = defaults.into_iter()
+            .map(|(index, default)| (offset + index, match default
+            {
+                Some(default) => self.expression(default),
+                None => Node::new(NodeKind::Constant(Expression::List(List::nil())), form)
+            }))
+            .collect::<Vec<_>>();
+
+        let node = match (defaults.is_empty(), self.body(body, form))
+        {
+            (true, node) => node,
+            (false, node) => Node::new(NodeKind::Defaults(defaults, node), form)
+        };
+
+        self.frames.pop();
+        self.lambdas -= 1;
+
+        Ok(Rc::new(Lambda
+        {
+            kind,
+            label,
+            params,
+            body: node,
+            source,
+            code: OnceCell::new(),
+        }))
+    }
+
+    /// The value of a `label`, where a lambda or macro written directly gets a slot for the label's name
+    fn labelled(&mut self, name: Atom, value: &Expression) -> Node
+    {
+        if let Expression::List(ref list) = *value
+        {
+            let kind = match list.car()
+            {
+                Some(&Expression::Atom(atom)) if atom.symbol().root() == Symbol::LAMBDA => Some(ClosureKind::Lambda),
+                Some(&Expression::Atom(atom)) if atom.symbol().root() == Symbol::MACRO => Some(ClosureKind::Macro),
+                _ => None
+            };
+
+            if let Some(kind) = kind
+            {
+                let mut arguments = list.iter();
+                arguments.next();
+
+                let lambda = with_body(if kind == ClosureKind::Lambda { "lambda" } else { "macro" }, arguments)
+                    .and_then(|([params], body)| self.lambda(kind, Some(name), params, body, value));
+
+                return Node::new(match lambda
+                {
+                    Ok(lambda) => NodeKind::Lambda(lambda),
+                    Err(err) => NodeKind::Error(err)
+                }, value);
+            }
+        }
+
+        self.expression(value)
+    }
+
+    fn call(&mut self, function: &Expression, arguments: ListIter) -> NodeKind
+    {
+        // a macro call is only left when checking code that hasn't been expanded, and none of it's
+        // names mean anything until it is
+        if self.expanded(function)
+        {
+            self.quiet += 1;
+
+            let function = self.expression(function);
+            let arguments = arguments.map(|arg| self.expression(arg)).collect();
+
+            self.quiet -= 1;
+
+            return NodeKind::Call(function, arguments);
+        }
+
+        let operative = self.operative(function);
+        let function = self.expression(function);
+
+        if operative
+        {
+            self.quiet += 1;
+        }
+
+        let arguments = arguments.map(|arg| self.expression(arg)).collect();
+
+        if operative
+        {
+            self.quiet -= 1;
+        }
+
+        NodeKind::Call(function, arguments)
+    }
+
+    /// Whether a call to `function` is a macro call that expansion would replace
+    fn expanded(&self, function: &Expression) -> bool
+    {
+        match *function
+        {
+            Expression::Atom(atom) =>
+            {
+                let symbol = atom.symbol().root();
+                self.macros.contains(&symbol) || self.env.is_some_and(|env| env.macro_named(symbol).is_some())
+            },
+            _ => false
+        }
+    }
+
+    /// Whether a call to `function` is known to take it's arguments as written
+    fn operative(&self, function: &Expression) -> bool
+    {
+        match *function
+        {
+            Expression::List(ref list) => matches!(list.car(), Some(&Expression::Atom(atom)) if atom.symbol().root() == Symbol::MACRO),
+            Expression::Atom(atom) => match self.env.and_then(|env| env.global(atom.symbol().root()))
+            {
+                Some(Expression::Closure(func)) => func.kind() == ClosureKind::Macro,
+                Some(Expression::Native(func)) => func.kind() == NativeKind::SpecialForm,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    use token::Token;
+
+    fn parse(input: &str) -> Vec<Expression>
+    {
+        Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+    }
+
+    fn body(node: &Node) -> Node
+    {
+        match *node.kind()
+        {
+            NodeKind::Lambda(ref lambda) => lambda.body.clone(),
+            NodeKind::Label(_, ref value) | NodeKind::Define(_, ref value) => body(value),
+            _ => panic!("expected a lambda, got {}", node.form())
+        }
+    }
+
+    fn address(node: &Node) -> Option<(usize, usize)>
+    {
+        match *node.kind()
+        {
+            NodeKind::Local(_, address) => Some((address.depth, address.index)),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn addresses()
+    {
+        let node = analyze(&parse("(lambda (a b) (lambda (c) (f c b a)))")[0]);
+
+        let call = match *body(&body(&node)).kind()
+        {
+            NodeKind::Call(ref function, ref arguments) =>
+            {
+                assert!(matches!(*function.kind(), NodeKind::Global(atom) if atom.as_str() == "f"));
+                arguments.iter().map(address).collect::<Vec<_>>()
+            },
+            _ => panic!("expected a call")
+        };
+
+        assert_eq!(vec![Some((0, 0)), Some((1, 1)), Some((1, 0))], call);
+
+        let labelled = analyze(&parse("(label f (lambda (x x) (f x)))")[0]);
+
+        match *body(&labelled).kind()
+        {
+            NodeKind::Call(ref function, ref arguments) =>
+            {
+                assert_eq!(Some((0, 0)), address(function));
+                assert_eq!(Some((0, 2)), address(&arguments[0]));
+            },
+            _ => panic!("expected a call")
+        }
+    }
+
+    #[test]
+    fn unbound_names()
+    {
+        let env = Environment::new();
+
+        let names = |input: &str| unbound(&parse(input), &env).iter()
+            .map(|err| format!("{}", err))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["1:19: Could not find substitution for atom 'y'"], names("(defun f (x) (car y))"));
+        assert_eq!(Vec::<String>::new(), names("(defun f (x) (g x))\n(defun g (x) (car x))"));
+        assert_eq!(Vec::<String>::new(), names("((macro (x) x) y) '(z) (quote w)"));
+        assert_eq!(Vec::<String>::new(), names("(defmacro m (x) `(car ,x))\n(m (y z))"));
+        assert_eq!(Vec::<String>::new(), names("(define-syntax s (syntax-rules () ((_ x) (car x))))\n(s (y z))"));
+        assert_eq!(vec!["2:22: Could not find substitution for atom 'b'"], names("(define a 1)\n((lambda (x) (cons x b)) a)"));
+    }
+}
@@ -0,0 +1,126 @@
+use expression::*;
+use error::EvalError;
+use eval::truthy;
+
+mod number;
+mod string;
+
+#[cfg(test)]
+mod test;
+
+pub fn builtins() -> Vec<Native>
+{
+    let mut natives = vec![
+        Native::new("atom", Arity::Exact(1), NativeKind::Function, atom),
+        Native::new("eq", Arity::Exact(2), NativeKind::Function, eq),
+        Native::new("eqv", Arity::Exact(2), NativeKind::Function, eqv),
+        Native::new("equal", Arity::Exact(2), NativeKind::Function, equal),
+        Native::new("car", Arity::Exact(1), NativeKind::Function, car),
+        Native::new("cdr", Arity::Exact(1), NativeKind::Function, cdr),
+        Native::new("cons", Arity::Exact(2), NativeKind::Function, cons),
+        Native::new("not", Arity::Exact(1), NativeKind::Function, not),
+        Native::new("gensym", Arity::Between(0, 1), NativeKind::Function, gensym),
+    ];
+
+    natives.extend(number::builtins());
+    natives.extend(string::builtins());
+
+    natives
+}
+
+fn truth(value: bool) -> Expression
+{
+    if value
+    {
+        Expression::Atom(Atom::from(Symbol::T))
+    }
+    else
+    {
+        Expression::List(List::nil())
+    }
+}
+
+fn atom(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(truth(!matches!(args[0], Expression::List(_))))
+}
+
+fn not(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(truth(!truthy(&args[0])))
+}
+
+/// A new atom that isn't equal to any other, even one with the same name, named with an optional prefix
+fn gensym(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let prefix = match args.first()
+    {
+        Some(Expression::Str(prefix)) => prefix.as_str().to_owned(),
+        Some(Expression::Atom(prefix)) => prefix.to_string(),
+        Some(other) => return Err(EvalError::type_error("gensym", "a string or an atom", other.clone())),
+        None => "g".to_owned()
+    };
+
+    Ok(Expression::Atom(Atom::from(Symbol::gensym(&prefix))))
+}
+
+fn eq(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(truth(match (&args[0], &args[1])
+    {
+        (Expression::Atom(left), Expression::Atom(right)) => left == right,
+        (Expression::List(left), Expression::List(right)) => left.is_empty() && right.is_empty(),
+        (Expression::Number(left), Expression::Number(right)) => left == right,
+        _ => false
+    }))
+}
+
+/// Like `eq`, but also true for the same pair, string or function
+fn eqv(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(truth(match (&args[0], &args[1])
+    {
+        (Expression::List(left), Expression::List(right)) => left.ptr_eq(right),
+        (Expression::Str(left), Expression::Str(right)) => left.ptr_eq(right),
+        (Expression::Native(left), Expression::Native(right)) => left == right,
+        (Expression::Closure(left), Expression::Closure(right)) => left == right,
+        _ => return eq(args)
+    }))
+}
+
+/// Whether two values have the same structure, comparing lists element by element and strings by their contents
+fn equal(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(truth(args[0] == args[1]))
+}
+
+fn car(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    match args[0]
+    {
+        Expression::List(ref arg) => match arg.car()
+        {
+            Some(car) => Ok(car.clone()),
+            None => Err(EvalError::type_error("car", "a non-empty list", args[0].clone()))
+        },
+        ref other => Err(EvalError::type_error("car", "a list", other.clone()))
+    }
+}
+
+fn cdr(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    match args[0]
+    {
+        Expression::List(ref arg) => match arg.cdr()
+        {
+            Some(cdr) => Ok(cdr.clone()),
+            None => Err(EvalError::type_error("cdr", "a non-empty list", args[0].clone()))
+        },
+        ref other => Err(EvalError::type_error("cdr", "a list", other.clone()))
+    }
+}
+
+fn cons(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(Expression::List(List::cons(args[0].clone(), args[1].clone())))
+}
@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+
+use expression::*;
+use error::{EvalError, ErrorKind};
+
+use super::truth;
+
+pub fn builtins() -> Vec<Native>
+{
+    vec![
+        Native::new("+", Arity::AtLeast(0), NativeKind::Function, add),
+        Native::new("-", Arity::AtLeast(1), NativeKind::Function, sub),
+        Native::new("*", Arity::AtLeast(0), NativeKind::Function, mul),
+        Native::new("/", Arity::AtLeast(1), NativeKind::Function, div),
+        Native::new("mod", Arity::Exact(2), NativeKind::Function, modulo),
+        Native::new("=", Arity::AtLeast(1), NativeKind::Function, |args| compare("=", args, |o| o == Ordering::Equal)),
+        Native::new("<", Arity::AtLeast(1), NativeKind::Function, |args| compare("<", args, |o| o == Ordering::Less)),
+        Native::new(">", Arity::AtLeast(1), NativeKind::Function, |args| compare(">", args, |o| o == Ordering::Greater)),
+        Native::new("<=", Arity::AtLeast(1), NativeKind::Function, |args| compare("<=", args, |o| o != Ordering::Greater)),
+        Native::new(">=", Arity::AtLeast(1), NativeKind::Function, |args| compare(">=", args, |o| o != Ordering::Less)),
+    ]
+}
+
+fn numbers(name: &str, args: &[Expression]) -> Result<Vec<Number>, EvalError>
+{
+    args.iter()
+        .map(|arg| match *arg
+        {
+            Expression::Number(n) => Ok(n),
+            ref other => Err(EvalError::type_error(name, "a number", other.clone()))
+        })
+        .collect()
+}
+
+fn add(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let sum = numbers("+", args)?.into_iter().fold(Number::Int(0), |a, b| a + b);
+
+    Ok(Expression::Number(sum))
+}
+
+fn sub(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let numbers = numbers("-", args)?;
+
+    let difference = match numbers.len()
+    {
+        1 => Number::Int(0) - numbers[0],
+        _ => numbers[1..].iter().fold(numbers[0], |a, &b| a - b)
+    };
+
+    Ok(Expression::Number(difference))
+}
+
+fn mul(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let product = numbers("*", args)?.into_iter().fold(Number::Int(1), |a, b| a * b);
+
+    Ok(Expression::Number(product))
+}
+
+fn div(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let numbers = numbers("/", args)?;
+
+    let (first, rest) = match numbers.len()
+    {
+        1 => (Number::Int(1), &numbers[..]),
+        _ => (numbers[0], &numbers[1..])
+    };
+
+    let mut quotient = first;
+
+    for &n in rest.iter()
+    {
+        quotient = match quotient.checked_div(n)
+        {
+            Some(q) => q,
+            None => return Err(EvalError::new(ErrorKind::DivisionByZero("/".to_owned())))
+        };
+    }
+
+    Ok(Expression::Number(quotient))
+}
+
+fn modulo(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let numbers = numbers("mod", args)?;
+
+    match numbers[0].checked_mod(numbers[1])
+    {
+        Some(n) => Ok(Expression::Number(n)),
+        None => Err(EvalError::new(ErrorKind::DivisionByZero("mod".to_owned())))
+    }
+}
+
+fn compare<F>(name: &str, args: &[Expression], accept: F) -> Result<Expression, EvalError>
+    where F: Fn(Ordering) -> bool
+{
+    let numbers = numbers(name, args)?;
+
+    for pair in numbers.windows(2)
+    {
+        match pair[0].compare(&pair[1])
+        {
+            Some(ordering) if accept(ordering) => {},
+            _ => return Ok(truth(false))
+        }
+    }
+
+    Ok(truth(true))
+}
@@ -0,0 +1,84 @@
+use expression::*;
+use error::EvalError;
+
+pub fn builtins() -> Vec<Native>
+{
+    vec![
+        Native::new("string-append", Arity::AtLeast(0), NativeKind::Function, string_append),
+        Native::new("string-length", Arity::Exact(1), NativeKind::Function, string_length),
+        Native::new("substring", Arity::Between(2, 3), NativeKind::Function, substring),
+        Native::new("string->symbol", Arity::Exact(1), NativeKind::Function, string_to_symbol),
+        Native::new("symbol->string", Arity::Exact(1), NativeKind::Function, symbol_to_string),
+    ]
+}
+
+fn string<'a>(name: &str, arg: &'a Expression) -> Result<&'a str, EvalError>
+{
+    match *arg
+    {
+        Expression::Str(ref s) => Ok(s.as_str()),
+        ref other => Err(EvalError::type_error(name, "a string", other.clone()))
+    }
+}
+
+fn index(name: &str, arg: &Expression) -> Result<usize, EvalError>
+{
+    match *arg
+    {
+        Expression::Number(Number::Int(i)) if i >= 0 => Ok(i as usize),
+        ref other => Err(EvalError::type_error(name, "a non-negative integer index", other.clone()))
+    }
+}
+
+fn string_append(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let mut result = String::new();
+
+    for arg in args.iter()
+    {
+        result.push_str(string("string-append", arg)?);
+    }
+
+    Ok(Expression::Str(Str::new(result)))
+}
+
+fn string_length(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let length = string("string-length", &args[0])?.chars().count();
+
+    Ok(Expression::Number(Number::Int(length as i64)))
+}
+
+fn substring(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    let s = string("substring", &args[0])?;
+    let length = s.chars().count();
+
+    let start = index("substring", &args[1])?;
+    let end = match args.get(2)
+    {
+        Some(arg) => index("substring", arg)?,
+        None => length
+    };
+
+    if start > end || end > length
+    {
+        return Err(EvalError::custom(format!("substring range {} to {} is out of bounds for a string of length {}", start, end, length)));
+    }
+
+    Ok(Expression::Str(Str::new(s.chars().skip(start).take(end - start).collect::<String>())))
+}
+
+fn string_to_symbol(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    Ok(Expression::Atom(Atom::new(string("string->symbol", &args[0])?)))
+}
+
+fn symbol_to_string(args: &[Expression]) -> Result<Expression, EvalError>
+{
+    match args[0]
+    {
+        Expression::Atom(ref atom) => Ok(Expression::Str(Str::new(atom.to_string()))),
+        ref other => Err(EvalError::type_error("symbol->string", "an atom", other.clone()))
+    }
+}
@@ -0,0 +1,95 @@
+use expression::*;
+use token::Token;
+use eval::{eval, eval_in, Environment};
+
+fn eval_str(input: &str) -> Expression
+{
+    eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap()
+}
+
+#[test]
+fn arithmetic()
+{
+    assert_eq!(Expression::Number(Number::Int(6)), eval_str("(+ 1 2 3)"));
+    assert_eq!(Expression::Number(Number::Int(0)), eval_str("(+)"));
+    assert_eq!(Expression::Number(Number::Int(-4)), eval_str("(- 4)"));
+    assert_eq!(Expression::Number(Number::Int(5)), eval_str("(- 10 3 2)"));
+    assert_eq!(Expression::Number(Number::Int(24)), eval_str("(* 2 3 4)"));
+    assert_eq!(Expression::Number(Number::Int(3)), eval_str("(/ 6 2)"));
+    assert_eq!(Expression::Number(Number::Float(2.5)), eval_str("(/ 5 2)"));
+    assert_eq!(Expression::Number(Number::Float(3.5)), eval_str("(+ 1 2.5)"));
+    assert_eq!(Expression::Number(Number::Int(2)), eval_str("(mod -7 3)"));
+    assert_eq!(Expression::Number(Number::Int(-1)), eval_str("(mod 7 -2)"));
+}
+
+#[test]
+fn arithmetic_errors()
+{
+    assert!(eval(Expression::parse(Token::lex("(/ 1 0)").unwrap()).unwrap()).is_err());
+    assert!(eval(Expression::parse(Token::lex("(mod 1 0)").unwrap()).unwrap()).is_err());
+    assert!(eval(Expression::parse(Token::lex("(+ 1 'a)").unwrap()).unwrap()).is_err());
+}
+
+#[test]
+fn overflow()
+{
+    let expected = Expression::Number(Number::Float(i64::MAX as f64 + 1.0));
+
+    assert_eq!(expected, eval_str("(+ 9223372036854775807 1)"));
+}
+
+#[test]
+fn comparison()
+{
+    let t = Expression::Atom(Atom::new("t"));
+    let nil = Expression::List(List::new(vec![]));
+
+    assert_eq!(t, eval_str("(< 1 2 3)"));
+    assert_eq!(nil, eval_str("(< 1 3 2)"));
+    assert_eq!(t, eval_str("(= 2 2.0)"));
+    assert_eq!(t, eval_str("(>= 3 3 1)"));
+    assert_eq!(nil, eval_str("(> 1 1)"));
+    assert_eq!(t, eval_str("(<= 1 1.5)"));
+}
+
+#[test]
+fn factorial()
+{
+    let input = "(defun fac (n) (cond ((= n 0) 1) ('t (* n (fac (- n 1))))))
+        (fac 20)
+        (fac 25)";
+
+    let mut env = Environment::new();
+    let results = Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+        .into_iter()
+        .map(|expr| eval_in(expr, &mut env).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(Expression::Number(Number::Int(2432902008176640000)), results[1]);
+
+    match results[2]
+    {
+        Expression::Number(Number::Float(f)) => assert!((f - 1.5511210043330986e25).abs() < 1e12),
+        ref other => panic!("expected a float, got {}", other)
+    }
+}
+
+#[test]
+fn strings()
+{
+    assert_eq!(Expression::Str(Str::new("foo bar")), eval_str("(string-append \"foo\" \" \" \"bar\")"));
+    assert_eq!(Expression::Str(Str::new("")), eval_str("(string-append)"));
+    assert_eq!(Expression::Number(Number::Int(3)), eval_str("(string-length \"λab\")"));
+    assert_eq!(Expression::Str(Str::new("ab")), eval_str("(substring \"λabc\" 1 3)"));
+    assert_eq!(Expression::Str(Str::new("bc")), eval_str("(substring \"λabc\" 2)"));
+    assert_eq!(Expression::Atom(Atom::new("foo")), eval_str("(string->symbol \"foo\")"));
+    assert_eq!(Expression::Str(Str::new("foo")), eval_str("(symbol->string 'foo)"));
+}
+
+#[test]
+fn string_errors()
+{
+    assert!(eval(Expression::parse(Token::lex("(substring \"abc\" 2 5)").unwrap()).unwrap()).is_err());
+    assert!(eval(Expression::parse(Token::lex("(string-length 'abc)").unwrap()).unwrap()).is_err());
+    assert!(eval(Expression::parse(Token::lex("(symbol->string \"abc\")").unwrap()).unwrap()).is_err());
+}
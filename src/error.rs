@@ -0,0 +1,163 @@
+use expression::{Expression, Atom, Arity};
+use token::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind
+{
+    UnboundAtom(Atom),
+    Arity
+    {
+        function: String,
+        expected: Arity,
+        found: usize,
+    },
+    Type
+    {
+        function: String,
+        expected: String,
+        found: Expression,
+    },
+    NotCallable(Expression),
+    /// A macro call that was still expanding to more macro calls after `expand::MAX_DEPTH` of them
+    ExpansionTooDeep(Expression),
+    DivisionByZero(String),
+    Syntax(String),
+    Custom(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalError(Box<EvalErrorInner>);
+
+#[derive(Clone, Debug, PartialEq)]
+struct EvalErrorInner
+{
+    kind: ErrorKind,
+    form: Option<Expression>,
+    backtrace: Vec<String>,
+}
+
+impl EvalError
+{
+    pub fn new(kind: ErrorKind) -> EvalError
+    {
+        EvalError(Box::new(EvalErrorInner
+        {
+            kind,
+            form: None,
+            backtrace: Vec::new(),
+        }))
+    }
+
+    pub fn unbound(atom: Atom) -> EvalError
+    {
+        EvalError::new(ErrorKind::UnboundAtom(atom))
+    }
+
+    pub fn arity<S: Into<String>>(function: S, expected: Arity, found: usize) -> EvalError
+    {
+        EvalError::new(ErrorKind::Arity
+        {
+            function: function.into(),
+            expected,
+            found,
+        })
+    }
+
+    pub fn type_error<S: Into<String>, T: Into<String>>(function: S, expected: T, found: Expression) -> EvalError
+    {
+        EvalError::new(ErrorKind::Type
+        {
+            function: function.into(),
+            expected: expected.into(),
+            found,
+        })
+    }
+
+    pub fn syntax<S: Into<String>>(msg: S) -> EvalError
+    {
+        EvalError::new(ErrorKind::Syntax(msg.into()))
+    }
+
+    pub fn custom<S: Into<String>>(msg: S) -> EvalError
+    {
+        EvalError::new(ErrorKind::Custom(msg.into()))
+    }
+
+    pub fn kind(&self) -> &ErrorKind
+    {
+        &self.0.kind
+    }
+
+    pub fn form(&self) -> Option<&Expression>
+    {
+        self.0.form.as_ref()
+    }
+
+    pub fn span(&self) -> Option<Span>
+    {
+        self.form().and_then(Expression::span)
+    }
+
+    pub fn backtrace(&self) -> &[String]
+    {
+        &self.0.backtrace
+    }
+
+    pub fn in_form(mut self, form: &Expression) -> EvalError
+    {
+        if self.0.form.is_none()
+        {
+            self.0.form = Some(form.clone());
+        }
+
+        self
+    }
+
+    pub fn called_from<S: Into<String>>(mut self, function: S) -> EvalError
+    {
+        self.0.backtrace.push(function.into());
+        self
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for ErrorKind
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            ErrorKind::UnboundAtom(ref atom) => write!(f, "Could not find substitution for atom '{}'", atom),
+            ErrorKind::Arity { ref function, ref expected, found } => write!(f, "{} expects {}, not {}", function, expected, found),
+            ErrorKind::Type { ref function, ref expected, ref found } => write!(f, "{} expects {}, not '{}'", function, expected, found),
+            ErrorKind::NotCallable(ref value) => write!(f, "Cannot call '{}' as if it were a function", value),
+            ErrorKind::ExpansionTooDeep(ref form) => write!(f, "Macro expansion too deep while expanding '{}'", form),
+            ErrorKind::DivisionByZero(ref function) => write!(f, "{} cannot divide by zero", function),
+            ErrorKind::Syntax(ref msg) => write!(f, "{}", msg),
+            ErrorKind::Custom(ref msg) => write!(f, "{}", msg)
+        }
+    }
+}
+
+impl fmt::Display for EvalError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if let Some(span) = self.span()
+        {
+            write!(f, "{}: ", span.start)?;
+        }
+
+        write!(f, "{}", self.0.kind)?;
+
+        for function in self.0.backtrace.iter()
+        {
+            write!(f, "\n    in {}", function)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ::std::error::Error for EvalError {}
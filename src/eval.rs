@@ -1,287 +1,422 @@
-use std::rc::Rc;
 use std::collections::HashMap;
 
 use expression::*;
+use error::{EvalError, ErrorKind};
+use analysis::{analyze, Address, Node, NodeKind};
+use expand::{expand, macroexpand, Macro};
+use builtins::builtins;
 
-pub fn eval(input: Expression) -> Result<Expression, String>
+#[derive(Clone, Debug)]
+pub struct Environment
 {
-    fn eval_inner(input: Expression, stack: &[Rc<HashMap<String, Expression>>]) -> Result<Expression, String>
+    globals: HashMap<Symbol, Expression>,
+    /// The macros defined with `defmacro` and `define-syntax`, which are kept apart from the globals
+    macros: HashMap<Symbol, Macro>,
+}
+
+impl Environment
+{
+    pub fn new() -> Environment
+    {
+        let mut env = Environment
+        {
+            globals: HashMap::new(),
+            macros: HashMap::new(),
+        };
+
+        for native in builtins()
+        {
+            env.define_native(native);
+        }
+
+        env
+    }
+
+    pub fn define<S: AsRef<str>>(&mut self, name: S, value: Expression)
+    {
+        self.globals.insert(Symbol::intern(name.as_ref()), value);
+    }
+
+    pub fn define_native(&mut self, native: Native)
+    {
+        self.globals.insert(Symbol::intern(native.name()), Expression::Native(native));
+    }
+
+    pub fn global(&self, name: Symbol) -> Option<&Expression>
+    {
+        self.globals.get(&name)
+    }
+
+    pub fn set_global(&mut self, name: Symbol, value: Expression)
+    {
+        self.globals.insert(name, value);
+    }
+
+    pub fn macro_named(&self, name: Symbol) -> Option<&Macro>
+    {
+        self.macros.get(&name)
+    }
+
+    pub fn set_macro(&mut self, name: Symbol, expander: Macro)
+    {
+        self.macros.insert(name, expander);
+    }
+}
+
+impl Default for Environment
+{
+    fn default() -> Environment
+    {
+        Environment::new()
+    }
+}
+
+pub fn eval(input: Expression) -> Result<Expression, EvalError>
+{
+    eval_in(input, &mut Environment::new())
+}
+
+pub fn eval_in(input: Expression, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    let input = expand(&input, env)?;
+
+    eval_inner(&analyze(&input), &Scope::new(), env)
+}
+
+enum Step
+{
+    Done(Expression),
+    Eval(Node),
+    /// Evaluates the node in a scope with new local variables
+    Enter(Node, Scope),
+    /// Evaluates a closure's body in the frame for a call to it, and the atom it was called through
+    Call(Closure, Scope, Option<Atom>),
+}
+
+fn eval_inner(node: &Node, scope: &Scope, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    let mut frame = None;
+
+    eval_tail(node, scope, env, &mut frame).map_err(|err| match frame
+    {
+        Some((func, called_as)) => err.called_from(func.call_name(called_as)),
+        None => err
+    })
+}
+
+fn eval_tail(node: &Node, scope: &Scope, env: &mut Environment, frame: &mut Option<(Closure, Option<Atom>)>) -> Result<Expression, EvalError>
+{
+    let mut node = node.clone();
+    let mut scope = scope.clone();
+
+    loop
     {
-        match input
+        match eval_step(&node, &scope, env).map_err(|err| err.in_form(node.form()))?
         {
-            Expression::Atom(atom) => match stack_lookup(&stack, atom.as_str())
+            Step::Done(value) => return Ok(value),
+            Step::Eval(next) => node = next,
+            Step::Enter(next, new_scope) =>
             {
-                Some(value) => Ok(value),
-                None => Err(format!("Could not find substitution for atom '{}'", atom))
+                node = next;
+                scope = new_scope;
             },
-            Expression::List(list) => match list.len()
+            Step::Call(func, new_scope, called_as) =>
             {
-                0 => Err(format!("The empty list has no meaning")),
-                _ => match &list.as_slice()[0]
-                {
-                    &Expression::Atom(ref first) => match first.as_str()
-                    {
-                        "quote" => match list.len()
-                        {
-                            2 => Ok(list.as_slice()[1].clone()),
-                            n => Err(format!("quote expects one argument, not {}", n - 1))
-                        },
-                        "atom" => match list.len()
-                        {
-                            2 => match eval_inner(list.as_slice()[1].clone(), stack)?
-                            {
-                                Expression::Atom(_) => Ok(Expression::Atom(Atom::new("t"))),
-                                _ => Ok(Expression::List(List::new(vec![])))
-                            },
-                            n => Err(format!("atom expects one argument, not {}", n - 1))
-                        },
-                        "eq" => match list.len()
-                        {
-                            3 => match (eval_inner(list.as_slice()[1].clone(), stack)?, eval_inner(list.as_slice()[2].clone(), stack)?)
-                            {
-                                (Expression::Atom(ref left), Expression::Atom(ref right)) if left.as_str() == right.as_str() => Ok(Expression::Atom(Atom::new("t"))),
-                                (Expression::List(ref left), Expression::List(ref right)) if left.len() == 0 && right.len() == 0 => Ok(Expression::Atom(Atom::new("t"))),
-                                _ => Ok(Expression::List(List::new(vec![])))
-                            },
-                            n => Err(format!("eq expects two arguments, not {}", n - 1))
-                        },
-                        "car" => match list.len()
-                        {
-                            2 => match eval_inner(list.as_slice()[1].clone(), stack)?
-                            {
-                                Expression::List(arg) => match arg.len()
-                                {
-                                    0 => Err(format!("car expects a non-empty list as an argument")),
-                                    _ => Ok(arg.as_slice()[0].clone())
-                                },
-                                _ => Err(format!("car expects a list as an argument"))
-                            },
-                            n => Err(format!("car expects one argument, not {}", n - 1))
-                        },
-                        "cdr" => match list.len()
-                        {
-                            2 => match eval_inner(list.as_slice()[1].clone(), stack)?
-                            {
-                                Expression::List(arg) => match arg.len()
-                                {
-                                    0 => Err(format!("cdr expects a non-empty list as an argument")),
-                                    _ => Ok(Expression::List(List::new(arg.as_slice()[1..arg.len()].to_vec())))
-                                },
-                                _ => Err(format!("cdr expects a list as an argument"))
-                            },
-                            n => Err(format!("cdr expects one argument, not {}", n - 1))
-                        },
-                        "cons" => match list.len()
-                        {
-                            3 => match (eval_inner(list.as_slice()[1].clone(), stack)?, eval_inner(list.as_slice()[2].clone(), stack)?)
-                            {
-                                (first, Expression::List(rest)) =>
-                                {
-                                    let mut tmp = vec![first];
-                                    for item in rest.as_slice().iter()
-                                    {
-                                        tmp.push(item.clone());
-                                    }
-                                    Ok(Expression::List(List::new(tmp)))
-                                },
-                                _ => Err(format!("cons expects an a list as it's second argument"))
-                            },
-                            n => Err(format!("cons expects two arguments, not {}", n - 1))
-                        },
-                        "cond" => match list.len()
-                        {
-                            1 => Err(format!("cond expects at least one argument")),
-                            _ =>
-                            {
-                                for item in list.as_slice()[1..list.len()].iter()
-                                {
-                                    match item
-                                    {
-                                        &Expression::List(ref pair) =>
-                                        {
-                                            if pair.len() != 2
-                                            {
-                                                return Err(format!("each pair in a cond argument list should have two elements, not {}", pair.len()));
-                                            }
-
-                                            match eval_inner(pair.as_slice()[0].clone(), stack)?
-                                            {
-                                                Expression::Atom(_) => return eval_inner(pair.as_slice()[1].clone(), stack),
-                                                _ => {}
-                                            }
-                                        },
-                                        _ => return Err(format!("cond expects pairs as arguments"))
-                                    }
-                                }
-
-                                Err(format!("no first element in any pair in cond was evaluated as true"))
-                            }
-                        },
-                        name => match stack_lookup(&stack, name)
-                        {
-                            Some(Expression::List(func)) => call_expression(stack, func, &list.as_slice()[1..list.len()]),
-                            Some(Expression::Atom(func)) => Err(format!("Cannot call atom '{}' as if it were a function", func)),
-                            None => Err(format!("Could not find substitution for atom '{}'", name))
-                        }
-                    },
-                    &Expression::List(ref first) => call_expression(stack, first.clone(), &list.as_slice()[1..list.len()])
-                }
+                node = func.lambda().body.clone();
+                scope = new_scope;
+                *frame = Some((func, called_as));
             }
         }
     }
+}
 
-    fn call_expression(stack: &[Rc<HashMap<String, Expression>>], func: List, arguments: &[Expression]) -> Result<Expression, String>
+// each form with more than a line or two of work is evaluated out of line, since a debug build
+// gives every arm of this match it's own stack space, and non-tail recursion goes through here
+fn eval_step(node: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    match *node.kind()
     {
-        match func.len()
+        NodeKind::Constant(ref value) => Ok(Step::Done(value.clone())),
+        NodeKind::Local(atom, address) => match scope.get(address)
+        {
+            Some(value) => Ok(Step::Done(value)),
+            // only a `letrec` variable used before it's value is ready
+            None => Err(EvalError::unbound(atom))
+        },
+        NodeKind::Global(atom) => match env.global(atom.symbol())
+        {
+            Some(value) => Ok(Step::Done(value.clone())),
+            None => Err(EvalError::unbound(atom))
+        },
+        NodeKind::Cond(ref clauses, ref malformed) => cond(clauses, malformed, scope, env),
+        NodeKind::If(ref test, ref then, ref otherwise) => branch(test, then, otherwise, scope, env),
+        NodeKind::And(ref parts) => sequence(parts, scope, env, Expression::Atom(Atom::from(Symbol::T)), |value| !truthy(value)),
+        NodeKind::Or(ref parts) => sequence(parts, scope, env, Expression::List(List::nil()), truthy),
+        NodeKind::Progn(ref parts) => sequence(parts, scope, env, Expression::List(List::nil()), |_| false),
+        NodeKind::Lambda(ref lambda) => Ok(Step::Done(Expression::Closure(Closure::new(lambda.clone(), scope.clone())))),
+        NodeKind::Label(name, ref value) => label(name, value, scope, env),
+        NodeKind::Define(name, ref value) => define(name, value, scope, env),
+        NodeKind::Defmacro(name, ref lambda) =>
         {
-            0 => Err(format!("Cannot call the empty list as a function")),
-            3 => match &func.as_slice()[0]
+            env.set_macro(name.symbol(), Macro::Procedure(Closure::new(lambda.clone(), scope.clone())));
+            Ok(Step::Done(Expression::Atom(name)))
+        },
+        NodeKind::Macroexpand(ref form, all) => expansion(form, all, scope, env),
+        NodeKind::Let(ref values, ref body) => bind(values, body, scope, env),
+        NodeKind::Letrec(ref values, ref body) => letrec(values, body, scope, env),
+        NodeKind::Cons(ref car, ref cdr) => pair(car, cdr, scope, env),
+        NodeKind::Append(ref list, ref rest) => splice(list, rest, scope, env),
+        NodeKind::Call(ref function, ref arguments) => call(node, function, arguments, scope, env),
+        NodeKind::Defaults(ref defaults, ref body) => fill(defaults, body, scope, env),
+        NodeKind::Error(ref err) => Err(err.clone())
+    }
+}
+
+fn cond(clauses: &[(Node, Option<Node>)], malformed: &Option<EvalError>, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    for (test, body) in clauses
+    {
+        let value = eval_inner(test, scope, env)?;
+
+        if truthy(&value)
+        {
+            return Ok(match *body
             {
-                &Expression::Atom(ref atom) => match atom.as_str()
-                {
-                    "lambda" => match &func.as_slice()[1]
-                    {
-                        &Expression::List(ref args) =>
-                        {
-                            if args.len() != arguments.len()
-                            {
-                                return Err(format!("Tried to call a function that accepts {} arguments with {} arguments", args.len(), arguments.len()))
-                            }
-
-                            if !args.as_slice().iter().all(|e| match e
-                            {
-                                &Expression::Atom(_) => true,
-                                _ => false
-                            })
-                            {
-                                return Err(format!("All elements in a function's argument list must be atoms"))
-                            }
-
-                            let argument_values =
-                            {
-                                let mut v = Vec::new();
-                                
-                                for arg in arguments.iter()
-                                {
-                                    v.push(eval_inner(arg.clone(), stack)?)
-                                }
-
-                                v
-                            };
-
-                            let subs = args.as_slice().iter()
-                                .map(|e| match e
-                                {
-                                    &Expression::Atom(ref atom) => atom.as_str().to_owned(),
-                                    _ => unreachable!()
-                                })
-                                .zip(argument_values.into_iter())
-                                .collect::<HashMap<_, _>>();
-                            
-                            eval_inner(func.as_slice()[2].clone(), &stack_push(stack, subs))
-                        },
-                        _ => Err(format!("The argument list of a function must be a list"))
-                    },
-                    "macro" => match &func.as_slice()[1]
-                    {
-                        &Expression::List(ref args) =>
-                        {
-                            if args.len() != arguments.len()
-                            {
-                                return Err(format!("Tried to call a macro that accepts {} arguments with {} arguments", args.len(), arguments.len()))
-                            }
-
-                            if !args.as_slice().iter().all(|e| match e
-                            {
-                                &Expression::Atom(_) => true,
-                                _ => false
-                            })
-                            {
-                                return Err(format!("All elements in a macros's argument list must be atoms"))
-                            }
-
-                            let argument_values =
-                            {
-                                let mut v = Vec::new();
-                                
-                                for arg in arguments.iter()
-                                {
-                                    v.push(arg.clone());
-                                }
-
-                                v
-                            };
-
-                            let subs = args.as_slice().iter()
-                                .map(|e| match e
-                                {
-                                    &Expression::Atom(ref atom) => atom.as_str().to_owned(),
-                                    _ => unreachable!()
-                                })
-                                .zip(argument_values.into_iter())
-                                .collect::<HashMap<_, _>>();
-                            
-                            eval_inner(func.as_slice()[2].clone(), &stack_push(stack, subs))
-                        },
-                        _ => Err(format!("The argument list of a macro must be a list"))
-                    },
-                    "label" => match &func.as_slice()[1]
-                    {
-                        &Expression::Atom(ref name) =>
-                        {
-                            let sub =
-                            {
-                                let mut h = HashMap::new();
-                                h.insert(name.as_str().to_owned(), Expression::List(func.clone()));
-                                h
-                            };
-
-                            call_expression(&stack_push(stack, sub), match &func.as_slice()[2]
-                            {
-                                &Expression::List(ref lambda) => lambda.clone(),
-                                _ => return Err(format!("The function in a label must be a list"))
-                            }, arguments)
-                        }
-                        _ => Err(format!("A list is not a valid label"))
-                    },
-                    name => Err(format!("the atom '{}' is not a way to create a function", name))
-                },
-                _ => Err(format!("A list cannot be the first element of a function"))
-            },
-            n => Err(format!("A list with {} elements cannot be a function", n))
+                Some(ref body) => Step::Eval(body.clone()),
+                None => Step::Done(value)
+            });
+        }
+    }
+
+    match *malformed
+    {
+        Some(ref err) => Err(err.clone()),
+        None => Ok(Step::Done(Expression::List(List::nil())))
+    }
+}
+
+/// Evaluates an `if`
+fn branch(test: &Node, then: &Node, otherwise: &Option<Node>, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    if truthy(&eval_inner(test, scope, env)?)
+    {
+        return Ok(Step::Eval(then.clone()));
+    }
+
+    Ok(match *otherwise
+    {
+        Some(ref otherwise) => Step::Eval(otherwise.clone()),
+        None => Step::Done(Expression::List(List::nil()))
+    })
+}
+
+fn label(name: Atom, value: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    match eval_inner(value, scope, env)?
+    {
+        Expression::Closure(func) => Ok(Step::Done(Expression::Closure(func.labelled(name)))),
+        other => Err(EvalError::type_error("label", "a lambda or a macro", other))
+    }
+}
+
+fn define(name: Atom, value: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let value = eval_inner(value, scope, env)?;
+    env.set_global(name.symbol(), value);
+    Ok(Step::Done(Expression::Atom(name)))
+}
+
+/// Evaluates a `macroexpand-1`, or a `macroexpand` if `all` is set
+fn expansion(form: &Node, all: bool, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let form = eval_inner(form, scope, env)?;
+    Ok(Step::Done(macroexpand(form, all, env)?))
+}
+
+fn bind(values: &[Node], body: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let values = evaluate(values, scope, env)?;
+    Ok(Step::Enter(body.clone(), scope.push(values)))
+}
+
+fn letrec(values: &[Node], body: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let inner = scope.reserve(values.len());
+
+    for (index, value) in values.iter().enumerate()
+    {
+        let value = eval_inner(value, &inner, env)?;
+        inner.set(index, value);
+    }
+
+    Ok(Step::Enter(body.clone(), inner))
+}
+
+/// Builds a pair, for a quasiquote
+fn pair(car: &Node, cdr: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let car = eval_inner(car, scope, env)?;
+    let cdr = eval_inner(cdr, scope, env)?;
+
+    Ok(Step::Done(Expression::List(List::cons(car, cdr))))
+}
+
+/// Copies a list in front of the rest of a quasiquote, for an unquote-splicing
+fn splice(list: &Node, rest: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let list = eval_inner(list, scope, env)?;
+    let rest = eval_inner(rest, scope, env)?;
+
+    Ok(Step::Done(append(list, rest)?))
+}
+
+/// Fills in the optional and keyword parameters that weren't passed, before a lambda's body
+fn fill(defaults: &[(usize, Node)], body: &Node, scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    for (index, default) in defaults
+    {
+        if scope.get(Address { depth: 0, index: *index }).is_none()
+        {
+            let value = eval_inner(default, scope, env)?;
+            scope.set(*index, value);
         }
     }
 
-    fn stack_lookup(stack: &[Rc<HashMap<String, Expression>>], name: &str) -> Option<Expression>
+    Ok(Step::Eval(body.clone()))
+}
+
+/// Evaluates `parts` in order, stopping early at a value that passes `stop`, and continuing with the
+/// last one as a tail call; an empty sequence gives `empty`
+fn sequence<F: Fn(&Expression) -> bool>(parts: &[Node], scope: &Scope, env: &mut Environment, empty: Expression, stop: F) -> Result<Step, EvalError>
+{
+    let (last, init) = match parts.split_last()
+    {
+        Some(split) => split,
+        None => return Ok(Step::Done(empty))
+    };
+
+    for part in init
     {
-        for scope in stack.iter().rev()
+        let value = eval_inner(part, scope, env)?;
+
+        if stop(&value)
         {
-            if let Some(expr) = scope.get(name)
-            {
-                return Some(expr.clone())
-            }
+            return Ok(Step::Done(value));
         }
-        return None
     }
 
-    fn stack_push(stack: &[Rc<HashMap<String, Expression>>], names: HashMap<String, Expression>) -> Vec<Rc<HashMap<String, Expression>>>
+    Ok(Step::Eval(last.clone()))
+}
+
+fn call(node: &Node, function: &Node, arguments: &[Node], scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let called_as = match *function.form()
+    {
+        Expression::Atom(atom) => Some(atom),
+        _ => None
+    };
+
+    match eval_inner(function, scope, env)?
+    {
+        Expression::Closure(func) => enter(func, called_as, node, arguments, scope, env),
+        Expression::Native(func) => native(&func, node, arguments, scope, env),
+        other => Err(EvalError::new(ErrorKind::NotCallable(other)))
+    }
+}
+
+/// The step into a closure's body, in the frame for a call to it
+fn enter(func: Closure, called_as: Option<Atom>, node: &Node, arguments: &[Node], scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    if !func.arity().accepts(arguments.len())
     {
-        let mut tmp = stack.to_vec();
-        tmp.push(Rc::new(names));
-        tmp
+        return Err(EvalError::arity(func.call_name(called_as), func.arity(), arguments.len()));
     }
 
-    eval_inner(input, &Vec::new())
+    let scope = match func.kind()
+    {
+        ClosureKind::Lambda => func.frame(called_as, evaluate(arguments, scope, env)?)?,
+        ClosureKind::Macro => func.frame(called_as, node.raw_arguments().cloned().collect())?
+    };
+
+    Ok(Step::Call(func, scope, called_as))
+}
+
+fn native(func: &Native, node: &Node, arguments: &[Node], scope: &Scope, env: &mut Environment) -> Result<Step, EvalError>
+{
+    let arguments = match func.kind()
+    {
+        NativeKind::Function => evaluate(arguments, scope, env)?,
+        NativeKind::SpecialForm => node.raw_arguments().cloned().collect()
+    };
+
+    Ok(Step::Done(func.call(&arguments).map_err(|err| err.called_from(func.name()))?))
+}
+
+/// Calls a closure with arguments it takes as they are, which is how a `defmacro` is expanded
+pub fn apply(func: &Closure, called_as: Atom, arguments: Vec<Expression>, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    let called_as = Some(called_as);
+
+    if !func.arity().accepts(arguments.len())
+    {
+        return Err(EvalError::arity(func.call_name(called_as), func.arity(), arguments.len()));
+    }
+
+    eval_inner(&func.lambda().body, &func.frame(called_as, arguments)?, env).map_err(|err| err.called_from(func.call_name(called_as)))
+}
+
+/// Copies the elements of `list` in front of `rest`, for an unquote-splicing
+pub fn append(list: Expression, rest: Expression) -> Result<Expression, EvalError>
+{
+    match list
+    {
+        Expression::List(ref list) if list.is_empty() => Ok(rest),
+        Expression::List(ref list) if list.is_proper() => Ok(Expression::List(List::dotted(list.to_vec(), rest))),
+        other => Err(EvalError::type_error("unquote-splicing", "a list", other))
+    }
+}
+
+/// Whether a value counts as true in a test, which is anything but a list
+pub fn truthy(value: &Expression) -> bool
+{
+    !matches!(*value, Expression::List(_))
+}
+
+fn evaluate(arguments: &[Node], scope: &Scope, env: &mut Environment) -> Result<Vec<Expression>, EvalError>
+{
+    // a loop rather than a `collect`, which would add several more frames per call in a debug build
+    let mut values = Vec::with_capacity(arguments.len());
+
+    for argument in arguments
+    {
+        values.push(eval_inner(argument, scope, env)?);
+    }
+
+    Ok(values)
 }
 
 #[cfg(test)]
 mod test
 {
-    use super::eval;
+    use std::thread;
+
+    use super::{eval, eval_in, Environment};
+
+    use error::ErrorKind;
 
     use expression::*;
     use token::*;
 
+    fn eval_all(input: &str) -> Vec<Expression>
+    {
+        let mut env = Environment::new();
+
+        Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+            .into_iter()
+            .map(|expr| eval_in(expr, &mut env).unwrap())
+            .collect()
+    }
+
     #[test]
     fn quote()
     {
@@ -289,7 +424,7 @@ mod test
 
         let expected = Expression::Atom(Atom::new("t"));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -303,8 +438,8 @@ mod test
         let expected_1 = Expression::Atom(Atom::new("t"));
         let expected_2 = Expression::List(List::new(vec![]));
 
-        let actual_1 = eval(Expression::parse(Token::lex(input_1)).unwrap()).unwrap();
-        let actual_2 = eval(Expression::parse(Token::lex(input_2)).unwrap()).unwrap();
+        let actual_1 = eval(Expression::parse(Token::lex(input_1).unwrap()).unwrap()).unwrap();
+        let actual_2 = eval(Expression::parse(Token::lex(input_2).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected_1, actual_1);
         assert_eq!(expected_2, actual_2);
@@ -321,9 +456,9 @@ mod test
         let expected_2 = Expression::Atom(Atom::new("t"));
         let expected_3 = Expression::List(List::new(vec![]));
 
-        let actual_1 = eval(Expression::parse(Token::lex(input_1)).unwrap()).unwrap();
-        let actual_2 = eval(Expression::parse(Token::lex(input_2)).unwrap()).unwrap();
-        let actual_3 = eval(Expression::parse(Token::lex(input_3)).unwrap()).unwrap();
+        let actual_1 = eval(Expression::parse(Token::lex(input_1).unwrap()).unwrap()).unwrap();
+        let actual_2 = eval(Expression::parse(Token::lex(input_2).unwrap()).unwrap()).unwrap();
+        let actual_3 = eval(Expression::parse(Token::lex(input_3).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected_1, actual_1);
         assert_eq!(expected_2, actual_2);
@@ -337,7 +472,7 @@ mod test
 
         let expected = Expression::Atom(Atom::new("a"));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -352,7 +487,7 @@ mod test
             Expression::Atom(Atom::new("c"))
         ]));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -368,9 +503,28 @@ mod test
             Expression::Atom(Atom::new("c"))
         ]));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
+
+        let pair = eval(Expression::parse(Token::lex("(cons 'a 'b)").unwrap()).unwrap()).unwrap();
+
+        assert_eq!("(a . b)", format!("{}", pair));
+        assert_eq!(Expression::parse(Token::lex("'(a . b)").unwrap()).unwrap(), Expression::List(List::new(vec![
+            Expression::Atom(Atom::new("quote")),
+            pair
+        ])));
+    }
+
+    #[test]
+    fn dotted_pairs()
+    {
+        let results = eval_all("(define p '(a b . c))
+            (cdr (cdr p))
+            (cdr '(a . (b . (c . ()))))
+            (cons 1 (cons 2 3))");
+
+        assert_eq!(vec!["p", "c", "(b c)", "(1 2 . 3)"], results.iter().map(|r| format!("{}", r)).collect::<Vec<_>>());
     }
 
     #[test]
@@ -382,11 +536,67 @@ mod test
         let expected_1 = Expression::Atom(Atom::new("b"));
         let expected_2 = Expression::Atom(Atom::new("a"));
 
-        let actual_1 = eval(Expression::parse(Token::lex(input_1)).unwrap()).unwrap();
-        let actual_2 = eval(Expression::parse(Token::lex(input_2)).unwrap()).unwrap();
+        let actual_1 = eval(Expression::parse(Token::lex(input_1).unwrap()).unwrap()).unwrap();
+        let actual_2 = eval(Expression::parse(Token::lex(input_2).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected_1, actual_1);
         assert_eq!(expected_2, actual_2);
+
+        let nil = Expression::List(List::nil());
+
+        assert_eq!(vec![nil.clone(), Expression::Atom(Atom::new("c")), Expression::Atom(Atom::new("b"))], eval_all("(cond ('() 'a))
+            (cond ('() 'a) ('t 'b 'c))
+            (cond ('b) ('t 'c))"));
+    }
+
+    #[test]
+    fn conditionals()
+    {
+        let input = "(if 't 'a 'b)
+            (if '() 'a)
+            (and 'a 'b)
+            (and 'a '() (car 'a))
+            (or '() 'b (car 'a))
+            (not '())
+            (when 'a 'b 'c)
+            (unless 'a 'b)
+            (progn (define x 'y) x)";
+
+        let atom = |name| Expression::Atom(Atom::new(name));
+        let nil = Expression::List(List::nil());
+
+        let expected = vec![atom("a"), nil.clone(), atom("b"), nil.clone(), atom("b"), atom("t"), atom("c"), nil, atom("y")];
+
+        assert_eq!(expected, eval_all(input));
+    }
+
+    #[test]
+    fn constants()
+    {
+        let input = "t
+            nil
+            ()
+            :key
+            (if nil 'a :b)";
+
+        let expected = vec![
+            Expression::Atom(Atom::new("t")),
+            Expression::List(List::nil()),
+            Expression::List(List::nil()),
+            Expression::Atom(Atom::new(":key")),
+            Expression::Atom(Atom::new(":b"))
+        ];
+
+        assert_eq!(expected, eval_all(input));
+
+        let mut env = Environment::new();
+
+        for input in ["(define t 'a)", "(defun nil () 'a)", "(lambda (:key) :key)", "(let ((t 'a)) t)", "(label nil (lambda () 'a))"]
+        {
+            let expr = Expression::parse(Token::lex(input).unwrap()).unwrap();
+
+            assert!(matches!(*eval_in(expr, &mut env).unwrap_err().kind(), ErrorKind::Type { .. }), "{} should fail", input);
+        }
     }
 
     #[test]
@@ -400,7 +610,7 @@ mod test
             Expression::Atom(Atom::new("c"))
         ]));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -416,7 +626,7 @@ mod test
             Expression::Atom(Atom::new("c"))
         ]));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -428,8 +638,229 @@ mod test
 
         let expected = Expression::Atom(Atom::new("b"));
 
-        let actual = eval(Expression::parse(Token::lex(input)).unwrap()).unwrap();
+        let actual = eval(Expression::parse(Token::lex(input).unwrap()).unwrap()).unwrap();
 
         assert_eq!(expected, actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn define()
+    {
+        let input = "(define x '(a b))
+            (define first (lambda (l) (car l)))
+            (first x)";
+
+        let expected = Expression::Atom(Atom::new("a"));
+
+        let mut env = Environment::new();
+        let mut actual = None;
+
+        for expr in Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+        {
+            actual = Some(eval_in(expr, &mut env).unwrap());
+        }
+
+        assert_eq!(Some(expected), actual);
+    }
+
+    #[test]
+    fn defun()
+    {
+        let input = "(defun last (l) (cond ((eq (cdr l) '()) (car l)) ('t (last (cdr l)))))
+            (last '(a b c))";
+
+        let expected = Expression::Atom(Atom::new("c"));
+
+        let mut env = Environment::new();
+        let mut actual = None;
+
+        for expr in Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+        {
+            actual = Some(eval_in(expr, &mut env).unwrap());
+        }
+
+        assert_eq!(Some(expected), actual);
+    }
+
+    #[test]
+    fn defmacro()
+    {
+        let input = "(defmacro swap (a b) `(cons ,b ,a))
+            (swap 'x 'y)
+            (macroexpand-1 '(swap 'x 'y))";
+
+        let expected = vec![
+            Expression::Atom(Atom::new("swap")),
+            Expression::List(List::cons(Expression::Atom(Atom::new("y")), Expression::Atom(Atom::new("x")))),
+            Expression::List(List::new(vec![
+                Expression::Atom(Atom::new("cons")),
+                Expression::List(List::new(vec![Expression::Atom(Atom::new("quote")), Expression::Atom(Atom::new("y"))])),
+                Expression::List(List::new(vec![Expression::Atom(Atom::new("quote")), Expression::Atom(Atom::new("x"))]))
+            ]))
+        ];
+
+        let mut env = Environment::new();
+
+        let actual = Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+            .into_iter()
+            .map(|expr| eval_in(expr, &mut env).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn closures()
+    {
+        let input = "(defun adder (n) (lambda (x) (+ x n)))
+            (define add2 (adder 2))
+            (define n 100)
+            (add2 3)
+            (((lambda (a) (lambda (b) (cons a b))) 'x) '(y))";
+
+        let expected_1 = Expression::Number(Number::Int(5));
+        let expected_2 = Expression::List(List::new(vec![
+            Expression::Atom(Atom::new("x")),
+            Expression::Atom(Atom::new("y"))
+        ]));
+
+        let actual = eval_all(input);
+
+        assert_eq!(expected_1, actual[3]);
+        assert_eq!(expected_2, actual[4]);
+    }
+
+    #[test]
+    fn bindings()
+    {
+        let input = "(define x 'outer)
+            (let ((x 1) (y x)) (cons x y))
+            (let* ((x 1) (y x)) (cons x y))
+            (letrec ((even (lambda (n) (cond ((= n 0) 't) ('t (odd (- n 1))))))
+                     (odd (lambda (n) (cond ((= n 0) '()) ('t (even (- n 1)))))))
+                (cons (even 10) (odd 10)))
+            (let () x)";
+
+        let int = |n| Expression::Number(Number::Int(n));
+
+        let expected = vec![
+            Expression::List(List::cons(int(1), Expression::Atom(Atom::new("outer")))),
+            Expression::List(List::cons(int(1), int(1))),
+            Expression::List(List::new(vec![Expression::Atom(Atom::new("t"))])),
+            Expression::Atom(Atom::new("outer"))
+        ];
+
+        assert_eq!(expected, eval_all(input)[1..]);
+    }
+
+    #[test]
+    fn callbacks()
+    {
+        let input = "(defun map (f l) (cond ((eq l '()) '()) ('t (cons (f (car l)) (map f (cdr l))))))
+            (defun scale (k l) (map (lambda (x) (* k x)) l))
+            (scale 3 '(1 2 3))";
+
+        let expected = Expression::List(List::new(vec![
+            Expression::Number(Number::Int(3)),
+            Expression::Number(Number::Int(6)),
+            Expression::Number(Number::Int(9))
+        ]));
+
+        let actual = eval_all(input);
+
+        assert_eq!(expected, actual[2]);
+    }
+
+    #[test]
+    fn tail_calls()
+    {
+        let input = "(defun count (n acc) (cond ((= n 0) acc) ('t (count (- n 1) (+ acc 1)))))
+            (count 20000 0)
+            (defun build (n acc) (cond ((= n 0) acc) ('t (build (- n 1) (cons n acc)))))
+            ((label walk (lambda (l n) (cond ((eq l '()) n) ('t (walk (cdr l) (+ n 1)))))) (build 2000 '()) 0)";
+
+        let expected_1 = Expression::Number(Number::Int(20000));
+        let expected_2 = Expression::Number(Number::Int(2000));
+
+        let actual = eval_all(input);
+
+        assert_eq!(expected_1, actual[1]);
+        assert_eq!(expected_2, actual[3]);
+    }
+
+    #[test]
+    fn deep_recursion()
+    {
+        let input = "(defun depth (n) (cond ((= n 0) 0) ('t (+ 1 (depth (- n 1))))))
+            (depth 1000)
+            (defun depth-if (n) (if (= n 0) 0 (+ 1 (depth-if (- n 1)))))
+            (depth-if 1000)
+            (defun depth-let (n) (let ((m (- n 1))) (when (>= m 0) (progn (and 't (or '() (depth-let m)))) n)))
+            (depth-let 1000)";
+
+        // calls that aren't in tail position use the native stack, so this runs with the 8MB a
+        // program's main thread gets, rather than the smaller stack of a test thread
+        let depths = thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || eval_all(input).iter().map(|result| format!("{}", result)).collect::<Vec<_>>())
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(vec!["depth", "1000", "depth-if", "1000", "depth-let", "1000"], depths);
+    }
+
+    #[test]
+    fn errors()
+    {
+        let input = "(defun f (x) (car x))
+            (defun g (x) (cons (f x) '()))";
+
+        let mut env = Environment::new();
+
+        for expr in Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+        {
+            eval_in(expr, &mut env).unwrap();
+        }
+
+        let err = |input: &str, env: &mut Environment| eval_in(Expression::parse(Token::lex(input).unwrap()).unwrap(), env).unwrap_err();
+
+        let type_error = err("(g 'a)", &mut env);
+
+        match *type_error.kind()
+        {
+            ErrorKind::Type { ref function, ref found, .. } =>
+            {
+                assert_eq!("car", function);
+                assert_eq!(&Expression::Atom(Atom::new("a")), found);
+            },
+            ref other => panic!("expected a type error, got {:?}", other)
+        }
+
+        assert_eq!(&["car".to_owned(), "f".to_owned(), "g".to_owned()], type_error.backtrace());
+        assert_eq!("(car x)", format!("{}", type_error.form().unwrap()));
+
+        assert_eq!(&ErrorKind::UnboundAtom(Atom::new("y")), err("(f y)", &mut env).kind());
+        assert!(matches!(*err("(cond ('() 'a) b)", &mut env).kind(), ErrorKind::Syntax(_)));
+        assert_eq!(&ErrorKind::NotCallable(Expression::Number(Number::Int(1))), err("(1 2)", &mut env).kind());
+
+        match *err("(f 'a 'b)", &mut env).kind()
+        {
+            ErrorKind::Arity { ref function, expected, found } =>
+            {
+                assert_eq!("f", function);
+                assert_eq!(Arity::Exact(1), expected);
+                assert_eq!(2, found);
+            },
+            ref other => panic!("expected an arity error, got {:?}", other)
+        }
+
+        let unbound = err("(cons 'a\n  (cons zz '()))", &mut env);
+
+        assert_eq!("zz", format!("{}", unbound.form().unwrap()));
+        assert_eq!(Position::new(2, 9), unbound.span().unwrap().start);
+        assert_eq!("2:9: Could not find substitution for atom 'zz'", format!("{}", unbound));
+
+        assert_eq!(Position::new(1, 14), type_error.span().unwrap().start);
+    }
+}
@@ -0,0 +1,354 @@
+//! Expands calls to the macros defined with `defmacro` and `define-syntax`, before expressions are analysed
+//!
+//! A `defmacro` is a function from the forms in a call to the code that replaces it, so expanding
+//! one runs it with the tree-walker, whichever backend evaluates the result. Each top level
+//! expression is expanded completely before any of it is evaluated, so a `defmacro` has to be
+//! evaluated by an earlier one to be used; a `define-syntax` takes effect as soon as it's expanded
+
+use std::rc::Rc;
+
+use expression::*;
+use error::{EvalError, ErrorKind};
+use eval::{apply, Environment};
+use analysis::wrapper;
+use syntax::Rules;
+
+/// How many macro calls can be expanded inside one another, or one after another by `macroexpand`,
+/// before expansion gives up, so that a macro which always expands to a call to itself is an error
+pub const MAX_DEPTH: usize = 500;
+
+/// What a macro's name expands with
+#[derive(Clone, Debug)]
+pub enum Macro
+{
+    /// A `defmacro`, called with the forms in the call
+    Procedure(Closure),
+    /// A `define-syntax`, which fills in a template
+    Rules(Rc<Rules>),
+}
+
+/// Expands every macro call in `expr`, and in the code they expand to
+pub fn expand(expr: &Expression, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    Expander { env, depth: 0, outermost: None }.expression(expr)
+}
+
+/// Expands `form` if it's a call to a macro, either once or until it isn't one any more, for
+/// `macroexpand-1` and `macroexpand`; the code it expands to isn't expanded any further
+pub fn macroexpand(mut form: Expression, all: bool, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    let call = form.clone();
+    let mut depth = 0;
+
+    while let Some(expansion) = expand_1(&form, env)?
+    {
+        depth += 1;
+
+        if depth > MAX_DEPTH
+        {
+            return Err(too_deep(&call));
+        }
+
+        form = expansion;
+
+        if !all
+        {
+            break;
+        }
+    }
+
+    Ok(form)
+}
+
+/// The code a call to a macro expands to, or `None` if `form` isn't one
+fn expand_1(form: &Expression, env: &mut Environment) -> Result<Option<Expression>, EvalError>
+{
+    let list = match *form
+    {
+        Expression::List(ref list) if list.is_proper() => list,
+        _ => return Ok(None)
+    };
+
+    let (name, expander) = match list.car()
+    {
+        Some(&Expression::Atom(name)) => match env.macro_named(name.symbol().root())
+        {
+            Some(expander) => (name, expander.clone()),
+            None => return Ok(None)
+        },
+        _ => return Ok(None)
+    };
+
+    let expansion = match expander
+    {
+        Macro::Procedure(func) => apply(&func, name, list.iter().skip(1).cloned().collect(), env),
+        Macro::Rules(rules) => rules.expand(list)
+    };
+
+    let expansion = expansion.map_err(|err| err.in_form(form))?;
+
+    // errors in a list the macro built point back to the call
+    Ok(Some(match (expansion, list.span())
+    {
+        (Expression::List(ref built), Some(span)) if !built.is_empty() && built.span().is_none() => Expression::List(built.clone().with_span(span)),
+        (expansion, _) => expansion
+    }))
+}
+
+fn too_deep(form: &Expression) -> EvalError
+{
+    EvalError::new(ErrorKind::ExpansionTooDeep(form.clone())).in_form(form)
+}
+
+/// Builds a copy of `list` from new elements, keeping it's span
+fn rebuild(list: &List, items: Vec<Expression>, tail: Expression) -> Expression
+{
+    let rebuilt = List::dotted(items, tail);
+
+    Expression::List(match list.span()
+    {
+        Some(span) => rebuilt.with_span(span),
+        None => rebuilt
+    })
+}
+
+fn nil() -> Expression
+{
+    Expression::List(List::nil())
+}
+
+struct Expander<'a>
+{
+    env: &'a mut Environment,
+    // how many macro calls are being expanded, each inside the expansion of the one before, and
+    // the first of them, as written, to blame if there are too many
+    depth: usize,
+    outermost: Option<Expression>,
+}
+
+impl<'a> Expander<'a>
+{
+    // forms with more than a line of work are expanded out of line, since a debug build gives every
+    // arm of the match it's own stack space, and expansion recurses through here
+    fn expression(&mut self, expr: &Expression) -> Result<Expression, EvalError>
+    {
+        // improper lists are left for the analysis to reject
+        let list = match *expr
+        {
+            Expression::List(ref list) if !list.is_empty() && list.is_proper() => list,
+            _ => return Ok(expr.clone())
+        };
+
+        let symbol = match list.car()
+        {
+            Some(&Expression::Atom(atom)) => atom.symbol().root(),
+            _ => return self.after(list, 0)
+        };
+
+        match symbol
+        {
+            Symbol::QUOTE => Ok(expr.clone()),
+            Symbol::QUASIQUOTE => match wrapper(list)
+            {
+                Some(_) => self.template(expr, 0),
+                None => Ok(expr.clone())
+            },
+            Symbol::LAMBDA | Symbol::MACRO => self.lambda(list, 1),
+            Symbol::DEFUN | Symbol::DEFMACRO => self.lambda(list, 2),
+            Symbol::LABEL | Symbol::DEFINE => self.after(list, 2),
+            Symbol::LET | Symbol::LET_STAR | Symbol::LETREC => self.bindings(list),
+            Symbol::DEFINE_SYNTAX => self.define_syntax(list, expr),
+            Symbol::COND => self.cond(list),
+            _ if self.env.macro_named(symbol).is_some() => self.call(expr),
+            // the arguments of macros and special forms are data, as written
+            _ => match self.env.global(symbol)
+            {
+                Some(Expression::Closure(func)) if func.kind() == ClosureKind::Macro => Ok(expr.clone()),
+                Some(Expression::Native(func)) if func.kind() == NativeKind::SpecialForm => Ok(expr.clone()),
+                _ => self.after(list, 1)
+            }
+        }
+    }
+
+    fn define_syntax(&mut self, list: &List, expr: &Expression) -> Result<Expression, EvalError>
+    {
+        let mut parts = list.iter().skip(1);
+
+        // a malformed one is left for the analysis to reject
+        if let (Some(&Expression::Atom(name)), Some(spec), None) = (parts.next(), parts.next(), parts.next())
+        {
+            let rules = Rules::parse(name.root(), spec).map_err(|err| err.in_form(expr))?;
+            self.env.set_macro(name.symbol().root(), Macro::Rules(Rc::new(rules)));
+        }
+
+        Ok(expr.clone())
+    }
+
+    fn cond(&mut self, list: &List) -> Result<Expression, EvalError>
+    {
+        let mut items = vec![Expression::Atom(Atom::from(Symbol::COND))];
+
+        for clause in list.iter().skip(1)
+        {
+            items.push(match *clause
+            {
+                Expression::List(ref clause) if clause.is_proper() => self.after(clause, 0)?,
+                ref other => other.clone()
+            });
+        }
+
+        Ok(rebuild(list, items, nil()))
+    }
+
+    /// Expands a call to a macro, and then the code it expands to
+    fn call(&mut self, expr: &Expression) -> Result<Expression, EvalError>
+    {
+        match self.depth
+        {
+            0 => self.outermost = Some(expr.clone()),
+            MAX_DEPTH => return Err(too_deep(self.outermost.as_ref().unwrap_or(expr))),
+            _ => ()
+        }
+
+        let expansion = expand_1(expr, self.env)?.expect("a call to a macro expands");
+
+        self.depth += 1;
+        let expanded = self.expression(&expansion);
+        self.depth -= 1;
+
+        expanded
+    }
+
+    /// Expands the elements of a proper `list` after the first `skip` of them
+    fn after(&mut self, list: &List, skip: usize) -> Result<Expression, EvalError>
+    {
+        let mut items = Vec::new();
+
+        for (index, item) in list.iter().enumerate()
+        {
+            items.push(if index < skip { item.clone() } else { self.expression(item)? });
+        }
+
+        Ok(rebuild(list, items, nil()))
+    }
+
+    /// Expands a form with a lambda list at `index`: the defaults of it's parameters, and the body after it
+    fn lambda(&mut self, list: &List, index: usize) -> Result<Expression, EvalError>
+    {
+        let mut items = Vec::new();
+
+        for (i, item) in list.iter().enumerate()
+        {
+            items.push(match i
+            {
+                _ if i < index => item.clone(),
+                _ if i == index => self.parameters(item)?,
+                _ => self.expression(item)?
+            });
+        }
+
+        Ok(rebuild(list, items, nil()))
+    }
+
+    /// Expands the default of each `(name default)` parameter in a lambda list
+    fn parameters(&mut self, params: &Expression) -> Result<Expression, EvalError>
+    {
+        let params = match *params
+        {
+            Expression::List(ref params) if !params.is_empty() => params,
+            _ => return Ok(params.clone())
+        };
+
+        let mut items = Vec::new();
+
+        for param in params.iter()
+        {
+            items.push(match *param
+            {
+                Expression::List(ref spec) if spec.is_proper() && spec.len() == 2 => self.after(spec, 1)?,
+                ref other => other.clone()
+            });
+        }
+
+        Ok(rebuild(params, items, params.tail().cloned().unwrap_or_else(nil)))
+    }
+
+    /// Expands the values in a `let` like form's bindings, and it's body
+    fn bindings(&mut self, list: &List) -> Result<Expression, EvalError>
+    {
+        let mut items = Vec::new();
+
+        for (index, item) in list.iter().enumerate()
+        {
+            items.push(match *item
+            {
+                Expression::List(ref bindings) if index == 1 && bindings.is_proper() =>
+                {
+                    let mut expanded = Vec::new();
+
+                    for binding in bindings.iter()
+                    {
+                        expanded.push(match *binding
+                        {
+                            Expression::List(ref binding) if binding.is_proper() && !binding.is_empty() => self.after(binding, 1)?,
+                            ref other => other.clone()
+                        });
+                    }
+
+                    rebuild(bindings, expanded, nil())
+                },
+                ref other if index < 2 => other.clone(),
+                ref other => self.expression(other)?
+            });
+        }
+
+        Ok(rebuild(list, items, nil()))
+    }
+
+    /// Expands the parts of a quasiquote template that are unquoted back to `depth` zero
+    fn template(&mut self, expr: &Expression, depth: usize) -> Result<Expression, EvalError>
+    {
+        let list = match *expr
+        {
+            Expression::List(ref list) if !list.is_empty() => list,
+            _ => return Ok(expr.clone())
+        };
+
+        if let Some((symbol, inner)) = wrapper(list)
+        {
+            let depth = match symbol
+            {
+                Symbol::QUASIQUOTE => depth + 1,
+                _ => depth - 1
+            };
+
+            let inner = match depth
+            {
+                0 => self.expression(inner)?,
+                _ => self.template(inner, depth)?
+            };
+
+            return Ok(rebuild(list, vec![Expression::Atom(Atom::from(symbol)), inner], nil()));
+        }
+
+        let mut items = Vec::new();
+        let mut rest = list;
+
+        let tail = loop
+        {
+            items.push(self.template(rest.car().expect("the rest of the list isn't empty"), depth)?);
+
+            match rest.cdr()
+            {
+                // `(a . ,b)` reads as `(a unquote b)`, which ends in an unquote form
+                Some(Expression::List(cdr)) if wrapper(cdr).is_some() => break self.template(&Expression::List(cdr.clone()), depth)?,
+                Some(Expression::List(cdr)) if cdr.is_empty() => break nil(),
+                Some(Expression::List(cdr)) => rest = cdr,
+                Some(other) => break other.clone(),
+                None => unreachable!("the rest of the list isn't empty")
+            }
+        };
+
+        Ok(rebuild(list, items, tail))
+    }
+}
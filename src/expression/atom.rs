@@ -1,19 +1,102 @@
-use std::rc::Rc;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Atom(Rc<String>);
+use super::Symbol;
+
+use token::Span;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Atom(Symbol, Option<Span>);
 
 impl Atom
 {
-    pub fn new<S: Into<String>>(s: S) -> Atom
+    pub fn new(s: &str) -> Atom
+    {
+        Atom(Symbol::intern(s), None)
+    }
+
+    pub fn spanned(s: &str, span: Span) -> Atom
+    {
+        Atom(Symbol::intern(s), Some(span))
+    }
+
+    pub fn symbol(&self) -> Symbol
+    {
+        self.0
+    }
+
+    /// The same atom, with a different symbol but in the same place
+    pub fn renamed(&self, symbol: Symbol) -> Atom
+    {
+        Atom(symbol, self.1)
+    }
+
+    /// The atom with any renaming by a hygienic macro undone
+    pub fn root(&self) -> Atom
     {
-        Atom(Rc::new(s.into()))
+        self.renamed(self.0.root())
     }
 
-    pub fn as_str<'a>(&'a self) -> &'a str
+    pub fn as_str(&self) -> &'static str
     {
         self.0.as_str()
     }
+
+    pub fn span(&self) -> Option<Span>
+    {
+        self.1
+    }
+
+    /// Whether this is a keyword like `:key`, which evaluates to itself
+    pub fn is_keyword(&self) -> bool
+    {
+        let name = self.as_str();
+
+        name.len() > 1 && name.starts_with(':')
+    }
+}
+
+impl From<Symbol> for Atom
+{
+    fn from(symbol: Symbol) -> Atom
+    {
+        Atom(symbol, None)
+    }
+}
+
+impl PartialEq for Atom
+{
+    fn eq(&self, other: &Atom) -> bool
+    {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        self.0.hash(state)
+    }
+}
+
+impl PartialOrd for Atom
+{
+    fn partial_cmp(&self, other: &Atom) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+/// Atoms sort by name, gensyms by their prefix; the symbol only separates aliases and gensyms from the atoms they're named after
+impl Ord for Atom
+{
+    fn cmp(&self, other: &Atom) -> Ordering
+    {
+        self.as_str().cmp(other.as_str()).then(self.0.cmp(&other.0))
+    }
 }
 
 use std::fmt;
@@ -22,6 +105,6 @@ impl fmt::Display for Atom
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        write!(f, "{}", self.as_str())
+        write!(f, "{}", self.0)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,410 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use super::{Expression, Atom, List, Arity};
+use error::EvalError;
+use analysis::{Address, Lambda};
+
+/// The local variables visible to an expression, as a chain of frames from innermost to outermost
+///
+/// Frames are shared, so pushing a frame onto a scope doesn't copy the frames below it. Variables
+/// are found by their `Address`, which the analysis pass works out from where they're bound
+#[derive(Clone, Debug, Default)]
+pub struct Scope(Option<Rc<Frame>>);
+
+#[derive(Debug)]
+struct Frame
+{
+    // slots are only empty, or changed after the frame is made, while a `letrec` is initialising them
+    slots: RefCell<Vec<Option<Expression>>>,
+    parent: Scope,
+    // whether the frame was reserved for a `letrec`, so it's closures may refer back to it
+    recursive: bool,
+}
+
+impl Scope
+{
+    pub fn new() -> Scope
+    {
+        Scope(None)
+    }
+
+    pub fn push(&self, slots: Vec<Expression>) -> Scope
+    {
+        self.frame(slots.into_iter().map(Some).collect())
+    }
+
+    /// Pushes a frame of `count` empty slots, to be filled in with `set`
+    pub(crate) fn reserve(&self, count: usize) -> Scope
+    {
+        self.make(vec![None; count], true)
+    }
+
+    pub(crate) fn frame(&self, slots: Vec<Option<Expression>>) -> Scope
+    {
+        self.make(slots, false)
+    }
+
+    fn make(&self, slots: Vec<Option<Expression>>, recursive: bool) -> Scope
+    {
+        Scope(Some(Rc::new(Frame
+        {
+            slots: RefCell::new(slots),
+            parent: self.clone(),
+            recursive,
+        })))
+    }
+
+    /// The scope without it's innermost frame
+    pub fn parent(&self) -> Scope
+    {
+        match self.0
+        {
+            Some(ref frame) => frame.parent.clone(),
+            None => Scope::new()
+        }
+    }
+
+    /// The value in a slot, if it's been filled in
+    pub(crate) fn get(&self, address: Address) -> Option<Expression>
+    {
+        let mut scope = self;
+
+        for _ in 0..address.depth
+        {
+            scope = &scope.0.as_ref()?.parent;
+        }
+
+        scope.0.as_ref()?.slots.borrow().get(address.index).cloned().flatten()
+    }
+
+    /// Fills in a slot of the innermost frame
+    pub(crate) fn set(&self, index: usize, value: Expression)
+    {
+        let frame = self.0.as_ref().expect("only frames that have been pushed have slots to set");
+
+        frame.slots.borrow_mut()[index] = Some(value);
+    }
+}
+
+impl Frame
+{
+    /// Empties the slots of a `letrec` frame once nothing can reach it
+    ///
+    /// A closure bound by `letrec` captures the frame that holds it, which is a reference cycle. Once
+    /// the only references left to such a frame are from closures that nothing but it's own slots
+    /// refer to, the slots are emptied to break the cycle. `frames` and `closure` are the references
+    /// that are about to be dropped, which are still counted
+    fn release(frame: &Rc<Frame>, frames: usize, closure: Option<&Rc<ClosureInner>>)
+    {
+        let cyclic = match frame.slots.try_borrow()
+        {
+            Ok(slots) => slots.iter().filter(|slot| match **slot
+            {
+                Some(Expression::Closure(ref func)) =>
+                {
+                    let dropping = closure.is_some_and(|closure| Rc::ptr_eq(closure, &func.0)) as usize;
+
+                    Rc::strong_count(&func.0) == 1 + dropping && func.0.scope.0.as_ref().is_some_and(|scope| Rc::ptr_eq(scope, frame))
+                },
+                _ => false
+            }).count(),
+            Err(_) => return
+        };
+
+        if Rc::strong_count(frame) == cyclic + frames
+        {
+            let slots = frame.slots.replace(Vec::new());
+            drop(slots);
+        }
+    }
+}
+
+impl Drop for Scope
+{
+    fn drop(&mut self)
+    {
+        if let Some(ref frame) = self.0
+        {
+            if frame.recursive
+            {
+                Frame::release(frame, 1, None);
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClosureKind
+{
+    Lambda,
+    Macro,
+}
+
+#[derive(Clone)]
+pub struct Closure(Rc<ClosureInner>);
+
+impl Drop for Closure
+{
+    fn drop(&mut self)
+    {
+        // the last other reference may be from a `letrec` frame's slot, leaving the frame unreachable
+        if Rc::strong_count(&self.0) > 1
+        {
+            if let Some(ref frame) = self.0.scope.0
+            {
+                if frame.recursive
+                {
+                    Frame::release(frame, 0, Some(&self.0));
+                }
+            }
+        }
+    }
+}
+
+struct ClosureInner
+{
+    lambda: Rc<Lambda>,
+    name: Option<Atom>,
+    scope: Scope,
+}
+
+impl Closure
+{
+    pub(crate) fn new(lambda: Rc<Lambda>, scope: Scope) -> Closure
+    {
+        Closure(Rc::new(ClosureInner
+        {
+            lambda,
+            name: None,
+            scope,
+        }))
+    }
+
+    pub fn labelled(&self, name: Atom) -> Closure
+    {
+        Closure(Rc::new(ClosureInner
+        {
+            lambda: self.0.lambda.clone(),
+            name: Some(name),
+            scope: self.0.scope.clone(),
+        }))
+    }
+
+    pub fn kind(&self) -> ClosureKind
+    {
+        self.0.lambda.kind
+    }
+
+    pub fn name(&self) -> Option<&Atom>
+    {
+        self.0.name.as_ref()
+    }
+
+    /// Every parameter, in the order of their slots in the frame for a call
+    pub fn params(&self) -> &[Atom]
+    {
+        &self.0.lambda.params.names
+    }
+
+    pub fn arity(&self) -> Arity
+    {
+        self.0.lambda.params.arity()
+    }
+
+    pub fn body(&self) -> &Expression
+    {
+        &self.0.lambda.source
+    }
+
+    pub fn scope(&self) -> &Scope
+    {
+        &self.0.scope
+    }
+
+    pub(crate) fn lambda(&self) -> &Rc<Lambda>
+    {
+        &self.0.lambda
+    }
+
+    /// The name a call shows up as in errors and backtraces: the closure's label, or else the atom
+    /// it was `called_as`, or else the closure itself. Calls only keep the atom, and this is only
+    /// worked out when an error needs it
+    pub(crate) fn call_name(&self, called_as: Option<Atom>) -> String
+    {
+        match self.0.name.or(called_as)
+        {
+            Some(name) => name.to_string(),
+            None => format!("{}", self)
+        }
+    }
+
+    /// The frame for a call with `arguments`, which already fit it's arity: the closure itself, if
+    /// it's lambda was written in a `label`, then a slot for each parameter. Optional and keyword
+    /// parameters that weren't passed are left empty, for the body to fill in with their defaults
+    pub(crate) fn frame(&self, called_as: Option<Atom>, arguments: Vec<Expression>) -> Result<Scope, EvalError>
+    {
+        let params = &self.0.lambda.params;
+        let mut slots = Vec::with_capacity(self.0.lambda.slots());
+
+        if self.0.lambda.label.is_some()
+        {
+            slots.push(Some(Expression::Closure(self.clone())));
+        }
+
+        let positional = slots.len() + params.required + params.optional;
+        let mut arguments = arguments.into_iter();
+
+        slots.extend(arguments.by_ref().take(params.required + params.optional).map(Some));
+        slots.resize(positional, None);
+
+        let rest = arguments.collect::<Vec<_>>();
+        let keys = params.keys();
+
+        if keys.is_empty()
+        {
+            if params.rest
+            {
+                slots.push(Some(Expression::List(List::new(rest))));
+            }
+
+            return Ok(self.0.scope.frame(slots));
+        }
+
+        if rest.len() % 2 != 0
+        {
+            return Err(EvalError::type_error(self.call_name(called_as), "a value after each keyword", Expression::List(List::new(rest))));
+        }
+
+        if params.rest
+        {
+            slots.push(Some(Expression::List(List::new(rest.clone()))));
+        }
+
+        let start = slots.len();
+        slots.resize(start + keys.len(), None);
+
+        for pair in rest.chunks(2)
+        {
+            let index = match pair[0]
+            {
+                Expression::Atom(keyword) => params.keywords.iter().position(|&key| key == keyword.symbol()),
+                _ => None
+            };
+
+            match index
+            {
+                // the first value passed for a keyword is the one it gets
+                Some(index) => if slots[start + index].is_none()
+                {
+                    slots[start + index] = Some(pair[1].clone());
+                },
+                None =>
+                {
+                    let expected = keys.iter().map(|key| format!(":{}", key)).collect::<Vec<_>>().join(" ");
+                    return Err(EvalError::type_error(self.call_name(called_as), format!("one of the keywords {}", expected), pair[0].clone()));
+                }
+            }
+        }
+
+        Ok(self.0.scope.frame(slots))
+    }
+}
+
+impl PartialEq for Closure
+{
+    fn eq(&self, other: &Closure) -> bool
+    {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Closure {}
+
+impl Hash for Closure
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        Rc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl PartialOrd for Closure
+{
+    fn partial_cmp(&self, other: &Closure) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+/// Closures are only equal to themselves, so they sort by where they are in memory
+impl Ord for Closure
+{
+    fn cmp(&self, other: &Closure) -> Ordering
+    {
+        Rc::as_ptr(&self.0).cmp(&Rc::as_ptr(&other.0))
+    }
+}
+
+use std::fmt;
+
+impl fmt::Debug for Closure
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("Closure")
+            .field("kind", &self.kind())
+            .field("name", &self.0.name)
+            .field("params", &self.params())
+            .field("body", self.body())
+            .finish()
+    }
+}
+
+impl fmt::Display for Closure
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let kind = match self.kind()
+        {
+            ClosureKind::Lambda => "lambda",
+            ClosureKind::Macro => "macro"
+        };
+
+        write!(f, "#<{} ", kind)?;
+
+        if let Some(ref name) = self.0.name
+        {
+            write!(f, "{} ", name)?;
+        }
+
+        let params = &self.0.lambda.params;
+        let optional = params.required;
+        let rest = optional + params.optional;
+        let keys = rest + params.rest as usize;
+
+        write!(f, "(")?;
+
+        for (i, param) in self.params().iter().enumerate()
+        {
+            if i != 0
+            {
+                write!(f, " ")?;
+            }
+
+            match i
+            {
+                _ if i == optional && params.optional > 0 => write!(f, "&optional ")?,
+                _ if i == rest && params.rest => write!(f, "&rest ")?,
+                _ if i == keys => write!(f, "&key ")?,
+                _ => {}
+            }
+
+            write!(f, "{}", param)?;
+        }
+
+        write!(f, ")>")
+    }
+}
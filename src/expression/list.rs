@@ -1,49 +1,333 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
 use super::Expression;
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct List(Vec<Expression>);
+use token::Span;
+
+#[derive(Clone)]
+pub struct List(Option<Rc<Pair>>, Option<Span>);
+
+struct Pair
+{
+    car: Expression,
+    cdr: Expression,
+}
 
 impl List
 {
+    pub fn nil() -> List
+    {
+        List(None, None)
+    }
+
+    pub fn cons(car: Expression, cdr: Expression) -> List
+    {
+        List(Some(Rc::new(Pair
+        {
+            car,
+            cdr,
+        })), None)
+    }
+
     pub fn new(v: Vec<Expression>) -> List
     {
-        List(v)
+        List::dotted(v, Expression::List(List::nil()))
+    }
+
+    /// Builds the list `(v0 v1 ... . tail)`; `v` may only be empty if `tail` is itself a list
+    pub fn dotted(mut v: Vec<Expression>, tail: Expression) -> List
+    {
+        let mut list = match tail
+        {
+            Expression::List(list) => list,
+            tail =>
+            {
+                let last = v.pop().expect("a dotted list needs an element before the dot");
+                List::cons(last, tail)
+            }
+        };
+
+        while let Some(item) = v.pop()
+        {
+            list = List::cons(item, Expression::List(list));
+        }
+
+        list
+    }
+
+    pub fn spanned(v: Vec<Expression>, span: Span) -> List
+    {
+        List::new(v).with_span(span)
+    }
+
+    pub fn with_span(mut self, span: Span) -> List
+    {
+        self.1 = Some(span);
+        self
     }
 
-    pub fn as_slice<'a>(&'a self) -> &'a [Expression]
+    pub fn span(&self) -> Option<Span>
     {
-        &self.0[0..self.0.len()]
+        self.1
+    }
+
+    pub fn car(&self) -> Option<&Expression>
+    {
+        self.0.as_ref().map(|pair| &pair.car)
+    }
+
+    pub fn cdr(&self) -> Option<&Expression>
+    {
+        self.0.as_ref().map(|pair| &pair.cdr)
+    }
+
+    /// Iterates over the elements of the list, stopping before the tail of an improper list
+    pub fn iter(&self) -> ListIter<'_>
+    {
+        ListIter(Some(self))
+    }
+
+    /// The last cdr of an improper list, or `None` if the list ends in `()`
+    pub fn tail(&self) -> Option<&Expression>
+    {
+        let mut list = self;
+
+        while let Some(ref pair) = list.0
+        {
+            match pair.cdr
+            {
+                Expression::List(ref rest) => list = rest,
+                ref tail => return Some(tail)
+            }
+        }
+
+        None
+    }
+
+    pub fn is_proper(&self) -> bool
+    {
+        self.tail().is_none()
+    }
+
+    pub fn to_vec(&self) -> Vec<Expression>
+    {
+        self.iter().cloned().collect()
     }
 
     pub fn len(&self) -> usize
     {
-        self.0.len()
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.0.is_none()
+    }
+
+    /// Whether both are the same list, rather than just equal ones; empty lists are always the same
+    pub fn ptr_eq(&self, other: &List) -> bool
+    {
+        match (&self.0, &other.0)
+        {
+            (Some(l), Some(r)) => Rc::ptr_eq(l, r),
+            (l, r) => l.is_none() && r.is_none()
+        }
     }
 }
 
-use std::fmt;
+#[derive(Clone)]
+pub struct ListIter<'a>(Option<&'a List>);
 
-impl fmt::Display for List
+impl<'a> Iterator for ListIter<'a>
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    type Item = &'a Expression;
+
+    fn next(&mut self) -> Option<&'a Expression>
     {
-        match self.len()
+        let pair = self.0.and_then(|list| list.0.as_ref())?;
+
+        self.0 = match pair.cdr
         {
-            0 => write!(f, "()"),
-            1 => write!(f, "({})", self.as_slice()[0]),
-            _ =>
+            Expression::List(ref rest) => Some(rest),
+            _ => None
+        };
+
+        Some(&pair.car)
+    }
+}
+
+impl<'a> IntoIterator for &'a List
+{
+    type Item = &'a Expression;
+    type IntoIter = ListIter<'a>;
+
+    fn into_iter(self) -> ListIter<'a>
+    {
+        self.iter()
+    }
+}
+
+impl PartialEq for List
+{
+    fn eq(&self, other: &List) -> bool
+    {
+        let (mut left, mut right) = (self, other);
+
+        loop
+        {
+            match (&left.0, &right.0)
             {
-                let mut temp = String::new();
+                (None, None) => return true,
+                (Some(l), Some(r)) =>
+                {
+                    if Rc::ptr_eq(l, r)
+                    {
+                        return true;
+                    }
 
-                for item in self.as_slice()[0..self.len() - 1].iter()
+                    if l.car != r.car
+                    {
+                        return false;
+                    }
+
+                    match (&l.cdr, &r.cdr)
+                    {
+                        (Expression::List(l), Expression::List(r)) =>
+                        {
+                            left = l;
+                            right = r;
+                        },
+                        (l, r) => return l == r
+                    }
+                },
+                _ => return false
+            }
+        }
+    }
+}
+
+impl Eq for List {}
+
+impl Hash for List
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        let mut count = 0;
+
+        for item in self.iter()
+        {
+            item.hash(state);
+            count += 1;
+        }
+
+        count.hash(state);
+        self.tail().hash(state);
+    }
+}
+
+impl PartialOrd for List
+{
+    fn partial_cmp(&self, other: &List) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+/// Lists sort element by element, like their pairs would by car and then cdr, so a proper list
+/// comes before any longer list it starts
+impl Ord for List
+{
+    fn cmp(&self, other: &List) -> Ordering
+    {
+        let (mut left, mut right) = (self, other);
+
+        loop
+        {
+            match (&left.0, &right.0)
+            {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(l), Some(r)) =>
                 {
-                    temp.push_str(&format!("{} ", item));
+                    if Rc::ptr_eq(l, r)
+                    {
+                        return Ordering::Equal;
+                    }
+
+                    match l.car.cmp(&r.car)
+                    {
+                        Ordering::Equal => {},
+                        unequal => return unequal
+                    }
+
+                    match (&l.cdr, &r.cdr)
+                    {
+                        (Expression::List(l), Expression::List(r)) =>
+                        {
+                            left = l;
+                            right = r;
+                        },
+                        (l, r) => return l.cmp(r)
+                    }
                 }
+            }
+        }
+    }
+}
+
+// long lists would otherwise be dropped recursively, one stack frame per pair
+impl Drop for Pair
+{
+    fn drop(&mut self)
+    {
+        let mut next = mem::replace(&mut self.cdr, Expression::List(List::nil()));
 
-                temp.push_str(&format!("{}", self.as_slice()[self.len() - 1]));
+        while let Expression::List(List(Some(pair), _)) = next
+        {
+            next = match Rc::try_unwrap(pair)
+            {
+                Ok(mut pair) => mem::replace(&mut pair.cdr, Expression::List(List::nil())),
+                Err(_) => break
+            };
+        }
+    }
+}
 
-                write!(f, "({})", temp)
+use std::fmt;
+
+impl fmt::Display for List
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "(")?;
+
+        for (i, item) in self.iter().enumerate()
+        {
+            if i > 0
+            {
+                write!(f, " ")?;
             }
+
+            write!(f, "{}", item)?;
+        }
+
+        if let Some(tail) = self.tail()
+        {
+            write!(f, " . {}", tail)?;
         }
+
+        write!(f, ")")
+    }
+}
+
+impl fmt::Debug for List
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "List{}", self)
     }
-}
\ No newline at end of file
+}
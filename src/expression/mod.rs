@@ -1,26 +1,58 @@
 mod atom;
+mod symbol;
 mod list;
+mod closure;
+mod native;
+mod number;
+mod string;
 
 #[cfg(test)]
 mod test;
 
 pub use self::atom::Atom;
-pub use self::list::List;
-
-use token::Token;
-
-#[derive(Clone, Debug, PartialEq)]
+pub use self::symbol::Symbol;
+pub use self::list::{List, ListIter};
+pub use self::closure::{Closure, ClosureKind, Scope};
+pub use self::native::{Native, NativeKind, NativeFn, Arity};
+pub use self::number::Number;
+pub use self::string::Str;
+
+use token::{Token, Position, Span};
+
+/// A value. Values are ordered first by kind, in the order of the variants here, and then by the
+/// order of that kind, so they can be sorted and used as keys however they mix
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Expression
 {
     Atom(Atom),
     List(List),
+    Number(Number),
+    Str(Str),
+    Native(Native),
+    Closure(Closure),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ParseError
 {
-    TooManyOpenParens,
-    TooManyCloseParens,
+    TooManyOpenParens(Position),
+    TooManyCloseParens(Position),
+    UnexpectedEndOfInput(Position),
+    MisplacedDot(Position),
+}
+
+impl ParseError
+{
+    pub fn position(&self) -> Position
+    {
+        match *self
+        {
+            ParseError::TooManyOpenParens(position) => position,
+            ParseError::TooManyCloseParens(position) => position,
+            ParseError::UnexpectedEndOfInput(position) => position,
+            ParseError::MisplacedDot(position) => position
+        }
+    }
 }
 
 use std::fmt;
@@ -31,63 +63,143 @@ impl fmt::Display for ParseError
     {
         match *self
         {
-            ParseError::TooManyOpenParens => write!(f, "Too many open parenthesis"),
-            ParseError::TooManyCloseParens => write!(f, "Too many close parenthesis")
+            ParseError::TooManyOpenParens(position) => write!(f, "{}: Too many open parenthesis, this one is never closed", position),
+            ParseError::TooManyCloseParens(position) => write!(f, "{}: Too many close parenthesis, this one has nothing to close", position),
+            ParseError::UnexpectedEndOfInput(position) => write!(f, "{}: Unexpected end of input", position),
+            ParseError::MisplacedDot(position) => write!(f, "{}: A dot can only come before the last element of a list", position)
         }
     }
 }
 
+impl ::std::error::Error for ParseError {}
+
 impl Expression
 {
-    pub fn parse(tokens: Vec<Token>) -> Result<Expression, ParseError>
+    pub fn span(&self) -> Option<Span>
+    {
+        match *self
+        {
+            Expression::Atom(ref atom) => atom.span(),
+            Expression::List(ref list) => list.span(),
+            _ => None
+        }
+    }
+
+    pub fn parse(tokens: Vec<(Token, Span)>) -> Result<Expression, ParseError>
+    {
+        Ok(parse_inner(&tokens, 0)?.0)
+    }
+
+    pub fn parse_all(tokens: Vec<(Token, Span)>) -> Result<Vec<Expression>, ParseError>
     {
-        pub fn parse_inner(tokens: &[Token], start: usize) -> Result<(Expression, usize), ParseError>
+        let mut index = 0;
+        let mut exprs = Vec::new();
+
+        while index < tokens.len()
         {
-            match &tokens[start]
+            let (expr, end) = parse_inner(&tokens, index)?;
+
+            index = end;
+
+            exprs.push(expr);
+        }
+
+        Ok(exprs)
+    }
+}
+
+fn end_of_input(tokens: &[(Token, Span)]) -> Position
+{
+    match tokens.last()
+    {
+        Some(&(_, span)) => span.end,
+        None => Position::new(1, 1)
+    }
+}
+
+fn parse_inner(tokens: &[(Token, Span)], start: usize) -> Result<(Expression, usize), ParseError>
+{
+    let (token, span) = match tokens.get(start)
+    {
+        Some(&(ref token, span)) => (token, span),
+        None => return Err(ParseError::UnexpectedEndOfInput(end_of_input(tokens)))
+    };
+
+    match *token
+    {
+        Token::Ident(ref name) => Ok((Expression::Atom(Atom::spanned(name.as_str(), span)), start + 1)),
+        Token::Int(i) => Ok((Expression::Number(Number::Int(i)), start + 1)),
+        Token::Float(f) => Ok((Expression::Number(Number::Float(f)), start + 1)),
+        Token::Str(ref s) => Ok((Expression::Str(Str::new(s.as_str())), start + 1)),
+        Token::OpenParen =>
+        {
+            let mut index = start + 1;
+            let mut list = Vec::new();
+            let mut tail = None;
+
+            loop
             {
-                &Token::Ident(ref name) => return Ok((Expression::Atom(Atom::new(name.as_str())), start + 1)),
-                &Token::OpenParen =>
+                if index >= tokens.len()
                 {
-                    let mut index = start + 1;
-                    let mut list = Vec::new();
+                    return Err(ParseError::TooManyOpenParens(span.start));
+                }
 
-                    loop
+                match tokens[index]
+                {
+                    (Token::CloseParen, _) => break,
+                    (_, dot) if tail.is_some() => return Err(ParseError::MisplacedDot(dot.start)),
+                    (Token::Dot, dot) =>
                     {
-                        if index >= tokens.len()
+                        // a dot needs an element before it and one after it
+                        if list.is_empty() || matches!(tokens.get(index + 1), Some((Token::CloseParen, _)))
                         {
-                            return Err(ParseError::TooManyOpenParens);
+                            return Err(ParseError::MisplacedDot(dot.start));
                         }
 
-                        match &tokens[index]
-                        {
-                            &Token::CloseParen => break,
-                            _ =>
-                            {
-                                let (expr, end) = parse_inner(tokens, index)?;
+                        let (expr, end) = parse_inner(tokens, index + 1)?;
 
-                                index = end;
+                        index = end;
 
-                                list.push(expr);
-                            }
-                        }
-                    }
+                        tail = Some(expr);
+                    },
+                    _ =>
+                    {
+                        let (expr, end) = parse_inner(tokens, index)?;
 
-                    return Ok((Expression::List(List::new(list)), index + 1))
-                },
-                &Token::CloseParen => return Err(ParseError::TooManyCloseParens),
-                &Token::Quote =>
-                {
-                    let (expr, end) = parse_inner(tokens, start + 1)?;
+                        index = end;
 
-                    return Ok((Expression::List(List::new(vec![
-                        Expression::Atom(Atom::new("quote")),
-                        expr
-                    ])), end))
+                        list.push(expr);
+                    }
                 }
             }
-        }
 
-        Ok(parse_inner(&tokens, 0)?.0)
+            let list = match tail
+            {
+                Some(tail) => List::dotted(list, tail),
+                None => List::new(list)
+            };
+
+            Ok((Expression::List(list.with_span(Span::new(span.start, tokens[index].1.end))), index + 1))
+        },
+        Token::CloseParen => Err(ParseError::TooManyCloseParens(span.start)),
+        Token::Dot => Err(ParseError::MisplacedDot(span.start)),
+        Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing =>
+        {
+            let name = match *token
+            {
+                Token::Quote => "quote",
+                Token::Quasiquote => "quasiquote",
+                Token::Unquote => "unquote",
+                _ => "unquote-splicing"
+            };
+
+            let (expr, end) = parse_inner(tokens, start + 1)?;
+
+            Ok((Expression::List(List::spanned(vec![
+                Expression::Atom(Atom::spanned(name, span)),
+                expr
+            ], Span::new(span.start, tokens[end - 1].1.end))), end))
+        }
     }
 }
 
@@ -95,10 +207,14 @@ impl fmt::Display for Expression
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        match self
+        match *self
         {
-            &Expression::Atom(ref atom) => write!(f, "{}", atom),
-            &Expression::List(ref list) => write!(f, "{}", list)
+            Expression::Atom(ref atom) => write!(f, "{}", atom),
+            Expression::List(ref list) => write!(f, "{}", list),
+            Expression::Number(ref number) => write!(f, "{}", number),
+            Expression::Str(ref string) => write!(f, "{}", string),
+            Expression::Native(ref native) => write!(f, "{}", native),
+            Expression::Closure(ref closure) => write!(f, "{}", closure)
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::rc::Rc;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use super::Expression;
+use error::EvalError;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Arity
+{
+    Exact(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity
+{
+    pub fn accepts(&self, n: usize) -> bool
+    {
+        match *self
+        {
+            Arity::Exact(e) => n == e,
+            Arity::AtLeast(min) => n >= min,
+            Arity::Between(min, max) => n >= min && n <= max
+        }
+    }
+}
+
+impl From<usize> for Arity
+{
+    fn from(n: usize) -> Arity
+    {
+        Arity::Exact(n)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NativeKind
+{
+    Function,
+    SpecialForm,
+}
+
+pub type NativeFn = dyn Fn(&[Expression]) -> Result<Expression, EvalError>;
+
+#[derive(Clone)]
+pub struct Native
+{
+    name: Rc<String>,
+    arity: Arity,
+    kind: NativeKind,
+    func: Rc<NativeFn>,
+}
+
+impl Native
+{
+    pub fn new<S, F>(name: S, arity: Arity, kind: NativeKind, func: F) -> Native
+        where S: Into<String>, F: Fn(&[Expression]) -> Result<Expression, EvalError> + 'static
+    {
+        Native
+        {
+            name: Rc::new(name.into()),
+            arity,
+            kind,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str
+    {
+        self.name.as_str()
+    }
+
+    pub fn arity(&self) -> Arity
+    {
+        self.arity
+    }
+
+    pub fn kind(&self) -> NativeKind
+    {
+        self.kind
+    }
+
+    /// Identifies the function, for comparing and hashing natives by identity
+    fn address(&self) -> usize
+    {
+        Rc::as_ptr(&self.func) as *const () as usize
+    }
+
+    pub fn call(&self, args: &[Expression]) -> Result<Expression, EvalError>
+    {
+        if !self.arity.accepts(args.len())
+        {
+            return Err(EvalError::arity(self.name.as_str(), self.arity, args.len()));
+        }
+
+        (self.func)(args)
+    }
+}
+
+impl PartialEq for Native
+{
+    fn eq(&self, other: &Native) -> bool
+    {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for Native {}
+
+impl Hash for Native
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        self.address().hash(state)
+    }
+}
+
+impl PartialOrd for Native
+{
+    fn partial_cmp(&self, other: &Native) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+/// Natives sort by name, and then by where they are in memory, which only has to be consistent
+/// for as long as they're alive
+impl Ord for Native
+{
+    fn cmp(&self, other: &Native) -> Ordering
+    {
+        self.name().cmp(other.name()).then(self.address().cmp(&other.address()))
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for Arity
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        fn arguments(n: usize) -> &'static str
+        {
+            if n == 1 { "argument" } else { "arguments" }
+        }
+
+        match *self
+        {
+            Arity::Exact(n) => write!(f, "{} {}", n, arguments(n)),
+            Arity::AtLeast(n) => write!(f, "at least {} {}", n, arguments(n)),
+            Arity::Between(min, max) => write!(f, "between {} and {} arguments", min, max)
+        }
+    }
+}
+
+impl fmt::Debug for Native
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl fmt::Display for Native
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "#<builtin {}>", self.name)
+    }
+}
@@ -0,0 +1,224 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops;
+
+/// A number, which is only equal to another of the same kind with the same value, like `eqv`
+/// compares them; `compare` is what the arithmetic comparisons use, which mixes the kinds
+#[derive(Copy, Clone, Debug)]
+pub enum Number
+{
+    Int(i64),
+    Float(f64),
+}
+
+impl Number
+{
+    pub fn as_float(&self) -> f64
+    {
+        match *self
+        {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f
+        }
+    }
+
+    pub fn is_zero(&self) -> bool
+    {
+        match *self
+        {
+            Number::Int(i) => i == 0,
+            Number::Float(f) => f == 0.0
+        }
+    }
+
+    pub fn checked_div(self, other: Number) -> Option<Number>
+    {
+        if other.is_zero()
+        {
+            return None;
+        }
+
+        Some(match (self, other)
+        {
+            (Number::Int(a), Number::Int(b)) => match (a.checked_rem(b), a.checked_div(b))
+            {
+                (Some(0), Some(n)) => Number::Int(n),
+                _ => Number::Float(a as f64 / b as f64)
+            },
+            (a, b) => Number::Float(a.as_float() / b.as_float())
+        })
+    }
+
+    pub fn checked_mod(self, other: Number) -> Option<Number>
+    {
+        if other.is_zero()
+        {
+            return None;
+        }
+
+        Some(match (self, other)
+        {
+            (Number::Int(a), Number::Int(b)) => match a.checked_rem(b)
+            {
+                Some(r) if r != 0 && (r < 0) != (b < 0) => Number::Int(r + b),
+                Some(r) => Number::Int(r),
+                None => Number::Int(0)
+            },
+            (a, b) =>
+            {
+                let (a, b) = (a.as_float(), b.as_float());
+                Number::Float(a - b * (a / b).floor())
+            }
+        })
+    }
+
+    pub fn compare(&self, other: &Number) -> Option<Ordering>
+    {
+        match (*self, *other)
+        {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+            (a, b) => a.as_float().partial_cmp(&b.as_float())
+        }
+    }
+}
+
+impl PartialEq for Number
+{
+    fn eq(&self, other: &Number) -> bool
+    {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+impl Hash for Number
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        match *self
+        {
+            Number::Int(i) => (0u8, i).hash(state),
+            Number::Float(f) => (1u8, f.to_bits()).hash(state)
+        }
+    }
+}
+
+impl PartialOrd for Number
+{
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders numbers by value, with an integer before a float of the same value. Floats follow
+/// `f64::total_cmp`, so `-0.0` comes before `0.0` and NaNs come after infinity, or before minus
+/// infinity if they're negative
+impl Ord for Number
+{
+    fn cmp(&self, other: &Number) -> Ordering
+    {
+        match (*self, *other)
+        {
+            (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+            (Number::Float(a), Number::Float(b)) => a.total_cmp(&b),
+            (Number::Int(a), Number::Float(b)) => mixed(a, b).then(Ordering::Less),
+            (Number::Float(a), Number::Int(b)) => mixed(b, a).reverse().then(Ordering::Greater)
+        }
+    }
+}
+
+/// Compares an integer with a float exactly, which converting the integer to a float wouldn't
+fn mixed(i: i64, f: f64) -> Ordering
+{
+    // 2^63, the first float above every i64
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+    if f.is_nan()
+    {
+        return if f.is_sign_negative() { Ordering::Greater } else { Ordering::Less };
+    }
+
+    if f >= LIMIT
+    {
+        return Ordering::Less;
+    }
+
+    if f < -LIMIT
+    {
+        return Ordering::Greater;
+    }
+
+    let whole = f.trunc();
+
+    i.cmp(&(whole as i64)).then_with(|| 0.0.partial_cmp(&(f - whole)).expect("the fraction isn't NaN"))
+}
+
+impl ops::Add for Number
+{
+    type Output = Number;
+
+    fn add(self, other: Number) -> Number
+    {
+        match (self, other)
+        {
+            (Number::Int(a), Number::Int(b)) => match a.checked_add(b)
+            {
+                Some(n) => Number::Int(n),
+                None => Number::Float(a as f64 + b as f64)
+            },
+            (a, b) => Number::Float(a.as_float() + b.as_float())
+        }
+    }
+}
+
+impl ops::Sub for Number
+{
+    type Output = Number;
+
+    fn sub(self, other: Number) -> Number
+    {
+        match (self, other)
+        {
+            (Number::Int(a), Number::Int(b)) => match a.checked_sub(b)
+            {
+                Some(n) => Number::Int(n),
+                None => Number::Float(a as f64 - b as f64)
+            },
+            (a, b) => Number::Float(a.as_float() - b.as_float())
+        }
+    }
+}
+
+impl ops::Mul for Number
+{
+    type Output = Number;
+
+    fn mul(self, other: Number) -> Number
+    {
+        match (self, other)
+        {
+            (Number::Int(a), Number::Int(b)) => match a.checked_mul(b)
+            {
+                Some(n) => Number::Int(n),
+                None => Number::Float(a as f64 * b as f64)
+            },
+            (a, b) => Number::Float(a.as_float() * b.as_float())
+        }
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for Number
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Float(n) => write!(f, "{:?}", n)
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Str(Rc<String>);
+
+impl Str
+{
+    pub fn new<S: Into<String>>(s: S) -> Str
+    {
+        Str(Rc::new(s.into()))
+    }
+
+    pub fn as_str(&self) -> &str
+    {
+        self.0.as_str()
+    }
+
+    /// Whether both are the same string, rather than just equal ones
+    pub fn ptr_eq(&self, other: &Str) -> bool
+    {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for Str
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "\"")?;
+
+        for c in self.as_str().chars()
+        {
+            match c
+            {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\r' => write!(f, "\\r")?,
+                '\0' => write!(f, "\\0")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => write!(f, "{}", c)?
+            }
+        }
+
+        write!(f, "\"")
+    }
+}
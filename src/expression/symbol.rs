@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// An interned name; two symbols are equal exactly when they were interned from the same string
+///
+/// A symbol is an entry in the table, and a number that's only non-zero for aliases and gensyms.
+/// Those are numbered off an existing entry instead of each adding one, since a program can make
+/// any number of them
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32, u32);
+
+// these are interned first, in this order, so the constants below refer to them
+const PREDEFINED: &[&str] = &["quote", "cond", "lambda", "macro", "label", "define", "defun", "t", "let", "let*", "letrec",
+    "progn", "begin", "if", "and", "or", "when", "unless", "nil",
+    "quasiquote", "unquote", "unquote-splicing", "defmacro", "macroexpand-1", "macroexpand",
+    "define-syntax", "syntax-rules", "...", "_", "&optional", "&rest", "&key"];
+
+impl Symbol
+{
+    pub const QUOTE: Symbol = Symbol(0, 0);
+    pub const COND: Symbol = Symbol(1, 0);
+    pub const LAMBDA: Symbol = Symbol(2, 0);
+    pub const MACRO: Symbol = Symbol(3, 0);
+    pub const LABEL: Symbol = Symbol(4, 0);
+    pub const DEFINE: Symbol = Symbol(5, 0);
+    pub const DEFUN: Symbol = Symbol(6, 0);
+    pub const T: Symbol = Symbol(7, 0);
+    pub const LET: Symbol = Symbol(8, 0);
+    pub const LET_STAR: Symbol = Symbol(9, 0);
+    pub const LETREC: Symbol = Symbol(10, 0);
+    pub const PROGN: Symbol = Symbol(11, 0);
+    pub const BEGIN: Symbol = Symbol(12, 0);
+    pub const IF: Symbol = Symbol(13, 0);
+    pub const AND: Symbol = Symbol(14, 0);
+    pub const OR: Symbol = Symbol(15, 0);
+    pub const WHEN: Symbol = Symbol(16, 0);
+    pub const UNLESS: Symbol = Symbol(17, 0);
+    pub const NIL: Symbol = Symbol(18, 0);
+    pub const QUASIQUOTE: Symbol = Symbol(19, 0);
+    pub const UNQUOTE: Symbol = Symbol(20, 0);
+    pub const UNQUOTE_SPLICING: Symbol = Symbol(21, 0);
+    pub const DEFMACRO: Symbol = Symbol(22, 0);
+    pub const MACROEXPAND_1: Symbol = Symbol(23, 0);
+    pub const MACROEXPAND: Symbol = Symbol(24, 0);
+    pub const DEFINE_SYNTAX: Symbol = Symbol(25, 0);
+    pub const SYNTAX_RULES: Symbol = Symbol(26, 0);
+    pub const ELLIPSIS: Symbol = Symbol(27, 0);
+    pub const WILDCARD: Symbol = Symbol(28, 0);
+    pub const OPTIONAL: Symbol = Symbol(29, 0);
+    pub const REST: Symbol = Symbol(30, 0);
+    pub const KEY: Symbol = Symbol(31, 0);
+
+    pub fn intern(name: &str) -> Symbol
+    {
+        let mut table = table();
+
+        if let Some(&symbol) = table.ids.get(name)
+        {
+            return symbol;
+        }
+
+        table.insert(name)
+    }
+
+    /// A new symbol with the same name that isn't interned, so it's only equal to itself; a
+    /// hygienic macro renames the identifiers it introduces to these
+    pub fn alias(self) -> Symbol
+    {
+        let mut table = table();
+
+        if self.1 == 0
+        {
+            let number = table.aliases.next();
+            return Symbol(self.0, number);
+        }
+
+        // an alias of an alias or a gensym, which only a macro that defines a macro makes, needs an
+        // entry of it's own to point back to it
+        let name = match self.is_gensym()
+        {
+            true => Box::leak(self.to_string().into_boxed_str()),
+            false => self.as_str()
+        };
+
+        table.push(name, Some(self), false)
+    }
+
+    /// A new symbol that isn't interned, named with `prefix` and a number
+    pub fn gensym(prefix: &str) -> Symbol
+    {
+        let mut table = table();
+
+        let base = match table.prefixes.get(prefix)
+        {
+            Some(&base) => base,
+            None =>
+            {
+                let name: &'static str = Box::leak(prefix.to_owned().into_boxed_str());
+                let base = table.push(name, None, true);
+                table.prefixes.insert(name, base);
+                base
+            }
+        };
+
+        let number = table.gensyms.next();
+
+        Symbol(base.0, number)
+    }
+
+    fn is_gensym(self) -> bool
+    {
+        self.1 != 0 && self.entry().prefix
+    }
+
+    /// The symbol this one is an alias of
+    pub fn original(self) -> Option<Symbol>
+    {
+        match self.1
+        {
+            0 => self.entry().original,
+            _ if self.entry().prefix => None,
+            _ => Some(Symbol(self.0, 0))
+        }
+    }
+
+    /// The interned symbol this one is an alias of, through any number of renamings
+    pub fn root(self) -> Symbol
+    {
+        let mut symbol = self;
+
+        while let Some(original) = symbol.original()
+        {
+            symbol = original;
+        }
+
+        symbol
+    }
+
+    /// The symbol's name, or a gensym's prefix without it's number
+    pub fn as_str(self) -> &'static str
+    {
+        self.entry().name
+    }
+
+    fn entry(self) -> &'static Entry
+    {
+        let (chunk, offset) = locate(self);
+
+        let chunk = match CHUNKS[chunk].get()
+        {
+            Some(chunk) => chunk,
+            // only the predefined symbols exist before the table has been initialised
+            None =>
+            {
+                table();
+                CHUNKS[chunk].get().expect("a symbol's chunk is allocated before the symbol is made")
+            }
+        };
+
+        chunk[offset].get().expect("a symbol's entry is filled in before the symbol is made")
+    }
+}
+
+struct Entry
+{
+    name: &'static str,
+    /// What the symbol is an alias of, if it is one
+    original: Option<Symbol>,
+    /// Whether this is a gensym prefix, so the symbols numbered off it are gensyms rather than aliases
+    prefix: bool,
+}
+
+type Chunk = Box<[OnceLock<Entry>]>;
+
+// the entries are kept in chunks that double in size, which are never moved or freed once they're
+// allocated, so reading a symbol's name doesn't need to lock the table
+const FIRST_CHUNK: u64 = 32;
+
+static CHUNKS: [OnceLock<Chunk>; 28] = [const { OnceLock::new() }; 28];
+
+/// The chunk a symbol's entry is in, and where it is in that chunk
+fn locate(symbol: Symbol) -> (usize, usize)
+{
+    let n = symbol.0 as u64 + FIRST_CHUNK;
+    let chunk = (n.ilog2() - FIRST_CHUNK.ilog2()) as usize;
+
+    (chunk, (n - (FIRST_CHUNK << chunk)) as usize)
+}
+
+/// The interned names and gensym prefixes, and how many entries there are; the entries themselves
+/// are in `CHUNKS`
+struct Table
+{
+    ids: HashMap<&'static str, Symbol>,
+    prefixes: HashMap<&'static str, Symbol>,
+    len: usize,
+    aliases: Counter,
+    gensyms: Counter,
+}
+
+/// Numbers aliases or gensyms from 1, since 0 is for symbols that are entries themselves
+struct Counter(u32);
+
+impl Counter
+{
+    fn next(&mut self) -> u32
+    {
+        self.0 = match self.0.checked_add(1)
+        {
+            Some(number) => number,
+            None => panic!("there are more aliases or gensyms than a symbol can number")
+        };
+
+        self.0
+    }
+}
+
+impl Table
+{
+    fn insert(&mut self, name: &str) -> Symbol
+    {
+        // interned names live for the rest of the program, like the symbols that refer to them
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        let symbol = self.push(name, None, false);
+
+        self.ids.insert(name, symbol);
+
+        symbol
+    }
+
+    /// Adds an entry without interning it's name
+    fn push(&mut self, name: &'static str, original: Option<Symbol>, prefix: bool) -> Symbol
+    {
+        let symbol = match u32::try_from(self.len)
+        {
+            Ok(index) => Symbol(index, 0),
+            Err(_) => panic!("there are more symbols than a symbol can number")
+        };
+
+        let (chunk, offset) = locate(symbol);
+        let chunk = CHUNKS[chunk].get_or_init(|| (0..FIRST_CHUNK << chunk).map(|_| OnceLock::new()).collect());
+
+        assert!(chunk[offset].set(Entry { name, original, prefix }).is_ok(), "each symbol is only added once");
+
+        self.len += 1;
+
+        symbol
+    }
+}
+
+static TABLE: Mutex<Option<Table>> = Mutex::new(None);
+
+fn table() -> TableGuard
+{
+    let mut guard = TABLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if guard.is_none()
+    {
+        let mut table = Table
+        {
+            ids: HashMap::new(),
+            prefixes: HashMap::new(),
+            len: 0,
+            aliases: Counter(0),
+            gensyms: Counter(0),
+        };
+
+        for name in PREDEFINED.iter()
+        {
+            table.insert(name);
+        }
+
+        *guard = Some(table);
+    }
+
+    TableGuard(guard)
+}
+
+struct TableGuard(MutexGuard<'static, Option<Table>>);
+
+impl ::std::ops::Deref for TableGuard
+{
+    type Target = Table;
+
+    fn deref(&self) -> &Table
+    {
+        self.0.as_ref().expect("the symbol table is initialised before it is used")
+    }
+}
+
+impl ::std::ops::DerefMut for TableGuard
+{
+    fn deref_mut(&mut self) -> &mut Table
+    {
+        self.0.as_mut().expect("the symbol table is initialised before it is used")
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for Symbol
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self.is_gensym()
+        {
+            true => write!(f, "{}{}", self.as_str(), self.1),
+            false => write!(f, "{}", self.as_str())
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use token::Token;
+use token::{Token, Position};
 
 #[test]
 fn parsing()
@@ -55,7 +55,234 @@ fn parsing()
         ]))
     ]));
 
-    let actual = Expression::parse(Token::lex(input)).unwrap();
+    let actual = Expression::parse(Token::lex(input).unwrap()).unwrap();
 
     assert_eq!(expected, actual);
-}
\ No newline at end of file
+}
+
+#[test]
+fn parsing_all()
+{
+    let input = "(car '(a b)) ; first
+        b
+        (c)";
+
+    let expected = vec![
+        Expression::List(List::new(vec![
+            Expression::Atom(Atom::new("car")),
+            Expression::List(List::new(vec![
+                Expression::Atom(Atom::new("quote")),
+                Expression::List(List::new(vec![
+                    Expression::Atom(Atom::new("a")),
+                    Expression::Atom(Atom::new("b"))
+                ]))
+            ]))
+        ])),
+        Expression::Atom(Atom::new("b")),
+        Expression::List(List::new(vec![
+            Expression::Atom(Atom::new("c"))
+        ]))
+    ];
+
+    let actual = Expression::parse_all(Token::lex(input).unwrap()).unwrap();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn string_display()
+{
+    let input = "\"say \\\"hi\\\"\\n\\ttab \\\\ \\u{7}\"";
+
+    let expected = "\"say \\\"hi\\\"\\n\\ttab \\\\ \\u{7}\"";
+
+    let actual = format!("{}", Expression::parse(Token::lex(input).unwrap()).unwrap());
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn parse_errors()
+{
+    let parse_all = |input: &str| Expression::parse_all(Token::lex(input).unwrap());
+
+    assert_eq!(Err(ParseError::TooManyOpenParens(Position::new(2, 5))), parse_all("(a b)\n    (c (d e)"));
+    assert_eq!(Err(ParseError::TooManyOpenParens(Position::new(1, 4))), parse_all("(a (b c"));
+    assert_eq!(Err(ParseError::TooManyCloseParens(Position::new(3, 3))), parse_all("(a b)\n(c)\n  )"));
+    assert_eq!(Err(ParseError::UnexpectedEndOfInput(Position::new(1, 5))), parse_all("'a '"));
+    assert_eq!(Err(ParseError::UnexpectedEndOfInput(Position::new(1, 1))), Expression::parse(Token::lex("").unwrap()));
+    assert_eq!(Err(ParseError::MisplacedDot(Position::new(1, 2))), parse_all("(. a)"));
+    assert_eq!(Err(ParseError::MisplacedDot(Position::new(1, 8))), parse_all("(a . b c)"));
+    assert_eq!(Err(ParseError::MisplacedDot(Position::new(1, 1))), parse_all(". a"));
+    assert_eq!(Err(ParseError::MisplacedDot(Position::new(1, 4))), parse_all("(a .)"));
+    assert_eq!(Err(ParseError::MisplacedDot(Position::new(2, 3))), parse_all("(a b\n  . )"));
+}
+
+#[test]
+fn spans()
+{
+    let span = |line, col, end_line, end_col| Some(Span::new(Position::new(line, col), Position::new(end_line, end_col)));
+
+    let expr = Expression::parse(Token::lex("(a\n  'b (c))").unwrap()).unwrap();
+
+    assert_eq!(span(1, 1, 2, 10), expr.span());
+
+    let list = match expr
+    {
+        Expression::List(list) => list,
+        other => panic!("expected a list, got {}", other)
+    };
+
+    let items = list.to_vec();
+
+    assert_eq!(span(1, 2, 1, 3), items[0].span());
+    assert_eq!(span(2, 3, 2, 5), items[1].span());
+    assert_eq!(span(2, 6, 2, 9), items[2].span());
+}
+
+#[test]
+fn dotted_lists()
+{
+    let display = |input: &str| format!("{}", Expression::parse(Token::lex(input).unwrap()).unwrap());
+
+    assert_eq!("(a . b)", display("(a . b)"));
+    assert_eq!("(a b . c)", display("(a . (b . c))"));
+    assert_eq!("(a b)", display("(a . (b . ()))"));
+    assert_eq!("((a . b) (c))", display("((a . b) . ((c)))"));
+
+    let list = List::dotted(vec![Expression::Atom(Atom::new("a"))], Expression::Number(Number::Int(1)));
+
+    assert!(!list.is_proper());
+    assert_eq!(1, list.len());
+    assert_eq!(Some(&Expression::Number(Number::Int(1))), list.tail());
+}
+
+#[test]
+fn reader_abbreviations()
+{
+    let display = |input: &str| format!("{}", Expression::parse(Token::lex(input).unwrap()).unwrap());
+
+    assert_eq!("(quote a)", display("'a"));
+    assert_eq!("(quasiquote (a (unquote b) (unquote-splicing c)))", display("`(a ,b ,@c)"));
+    assert_eq!("(quasiquote (a unquote b))", display("`(a . ,b)"));
+}
+
+#[test]
+fn long_lists()
+{
+    let items = (0..200_000).map(|i| Expression::Number(Number::Int(i))).collect::<Vec<_>>();
+
+    let left = List::new(items.clone());
+    let right = List::new(items);
+
+    assert_eq!(left, right);
+    assert_eq!(200_000, left.len());
+}
+
+#[test]
+fn symbols()
+{
+    assert_eq!(Symbol::intern("some-symbol"), Symbol::intern("some-symbol"));
+    assert_ne!(Symbol::intern("some-symbol"), Symbol::intern("other-symbol"));
+    assert_eq!("some-symbol", Symbol::intern("some-symbol").as_str());
+
+    assert_eq!(Atom::new("x"), Atom::spanned("x", Span::new(Position::new(1, 1), Position::new(1, 2))));
+    assert_eq!(Symbol::intern("x"), Atom::new("x").symbol());
+
+    let predefined = [Symbol::QUOTE, Symbol::COND, Symbol::LAMBDA, Symbol::MACRO, Symbol::LABEL, Symbol::DEFINE, Symbol::DEFUN, Symbol::T, Symbol::LET, Symbol::LET_STAR, Symbol::LETREC,
+        Symbol::PROGN, Symbol::BEGIN, Symbol::IF, Symbol::AND, Symbol::OR, Symbol::WHEN, Symbol::UNLESS, Symbol::NIL,
+        Symbol::QUASIQUOTE, Symbol::UNQUOTE, Symbol::UNQUOTE_SPLICING, Symbol::DEFMACRO, Symbol::MACROEXPAND_1, Symbol::MACROEXPAND,
+        Symbol::DEFINE_SYNTAX, Symbol::SYNTAX_RULES, Symbol::ELLIPSIS, Symbol::WILDCARD, Symbol::OPTIONAL, Symbol::REST, Symbol::KEY];
+
+    for (symbol, name) in predefined.iter().zip(["quote", "cond", "lambda", "macro", "label", "define", "defun", "t", "let", "let*", "letrec",
+        "progn", "begin", "if", "and", "or", "when", "unless", "nil",
+        "quasiquote", "unquote", "unquote-splicing", "defmacro", "macroexpand-1", "macroexpand",
+        "define-syntax", "syntax-rules", "...", "_", "&optional", "&rest", "&key"].iter())
+    {
+        assert_eq!(*symbol, Symbol::intern(name));
+        assert_eq!(*name, symbol.as_str());
+    }
+
+    let x = Symbol::intern("x");
+    let alias = x.alias();
+
+    assert_ne!(x, alias);
+    assert_eq!("x", alias.as_str());
+    assert_eq!(Some(x), alias.original());
+    assert_eq!(x, alias.alias().root());
+    assert_eq!(None, x.original());
+    assert_ne!(Symbol::gensym("g"), Symbol::gensym("g"));
+    assert_ne!(Symbol::intern(&Symbol::gensym("x").to_string()), Symbol::gensym("x"));
+
+    // aliases and gensyms share the name they're made from, rather than each keeping a copy
+    let (first, second) = (Symbol::gensym("shared"), Symbol::gensym("shared"));
+
+    assert!(::std::ptr::eq(x.as_str(), alias.as_str()));
+    assert!(::std::ptr::eq(first.as_str(), second.as_str()));
+    assert!(first.to_string().starts_with("shared") && first.to_string() != second.to_string());
+    assert_eq!(None, first.original());
+
+    let renamed = first.alias();
+
+    assert_eq!(first.to_string(), renamed.to_string());
+    assert_eq!(Some(first), renamed.original());
+    assert_eq!(first, renamed.alias().root());
+
+    // enough symbols to fill several of the table's chunks, read from other threads
+    let names = (0..3000).map(|n| format!("many-{}", n)).collect::<Vec<_>>();
+    let symbols = names.iter().map(|name| Symbol::intern(name).alias()).collect::<Vec<_>>();
+
+    ::std::thread::spawn(move || for (symbol, name) in symbols.iter().zip(names.iter())
+    {
+        assert_eq!(name.as_str(), symbol.as_str());
+        assert_eq!(Symbol::intern(name), symbol.root());
+    }).join().unwrap();
+}
+
+#[test]
+fn scopes()
+{
+    use analysis::Address;
+
+    let int = |value| Expression::Number(Number::Int(value));
+    let at = |depth, index| Address { depth, index };
+
+    let outer = Scope::new().push(vec![int(1)]);
+    let inner = outer.push(vec![int(2), int(3)]).push(vec![int(4)]);
+
+    assert_eq!(Some(int(4)), inner.get(at(0, 0)));
+    assert_eq!(Some(int(3)), inner.get(at(1, 1)));
+    assert_eq!(Some(int(1)), inner.get(at(2, 0)));
+    assert_eq!(Some(int(1)), outer.get(at(0, 0)));
+    assert_eq!(None, outer.get(at(0, 1)));
+    assert_eq!(None, outer.get(at(1, 0)));
+
+    let reserved = outer.reserve(2);
+    reserved.set(1, int(5));
+
+    assert_eq!(None, reserved.get(at(0, 0)));
+    assert_eq!(Some(int(5)), reserved.get(at(0, 1)));
+    assert_eq!(Some(int(1)), reserved.parent().get(at(0, 0)));
+}
+
+#[test]
+// `Expression`'s hash and ordering never read the `RefCell`s in a closure's frames
+#[allow(clippy::mutable_key_type)]
+fn ordering()
+{
+    use std::collections::HashSet;
+
+    let parse_all = |input: &str| Expression::parse_all(Token::lex(input).unwrap()).unwrap();
+
+    let mut values = parse_all("(a c) 2.5 b \"s\" 2 (a b . c) -1.5 (a b) () a 2.0 (a (b))");
+    values.sort();
+
+    let sorted = values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(vec!["a", "b", "()", "(a b . c)", "(a b)", "(a c)", "(a (b))", "-1.5", "2", "2.0", "2.5", "\"s\""], sorted);
+
+    // values are equal, and hash the same, whatever their spans
+    let distinct = parse_all("(a (b 1)) (a\n (b 1)) 1 1.0 -0.0 0.0 \"a\" a \"a\"").into_iter().collect::<HashSet<_>>();
+
+    assert_eq!(7, distinct.len());
+}
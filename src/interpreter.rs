@@ -0,0 +1,266 @@
+use token::{Token, LexError, Span};
+use expression::{Expression, ParseError, Native, NativeKind, Arity};
+use error::EvalError;
+use eval::{eval_in, Environment};
+use analysis::unbound;
+use vm;
+
+#[derive(Clone, Debug)]
+pub enum Error
+{
+    Lex(LexError),
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+use std::fmt;
+use std::error::Error as StdError;
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Error::Lex(ref err) => write!(f, "{}", err),
+            Error::Parse(ref err) => write!(f, "{}", err),
+            Error::Eval(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl StdError for Error
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)>
+    {
+        match *self
+        {
+            Error::Lex(ref err) => Some(err),
+            Error::Parse(ref err) => Some(err),
+            Error::Eval(ref err) => Some(err)
+        }
+    }
+}
+
+impl Error
+{
+    pub fn span(&self) -> Option<Span>
+    {
+        match *self
+        {
+            Error::Lex(ref err) => Some(Span::new(err.position(), err.position())),
+            Error::Parse(ref err) => Some(Span::new(err.position(), err.position())),
+            Error::Eval(ref err) => err.span()
+        }
+    }
+}
+
+impl From<LexError> for Error
+{
+    fn from(err: LexError) -> Error
+    {
+        Error::Lex(err)
+    }
+}
+
+impl From<ParseError> for Error
+{
+    fn from(err: ParseError) -> Error
+    {
+        Error::Parse(err)
+    }
+}
+
+impl From<EvalError> for Error
+{
+    fn from(err: EvalError) -> Error
+    {
+        Error::Eval(err)
+    }
+}
+
+/// How an `Interpreter` evaluates expressions; both backends give the same results
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Backend
+{
+    /// Walks the expression tree directly, this is the reference implementation
+    #[default]
+    TreeWalker,
+    /// Compiles each expression, and each closure body when it's first called, to bytecode for a stack machine
+    Bytecode,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Interpreter
+{
+    env: Environment,
+    backend: Backend,
+}
+
+impl Interpreter
+{
+    pub fn new() -> Interpreter
+    {
+        Interpreter::with_backend(Backend::TreeWalker)
+    }
+
+    pub fn with_backend(backend: Backend) -> Interpreter
+    {
+        Interpreter
+        {
+            env: Environment::new(),
+            backend,
+        }
+    }
+
+    pub fn backend(&self) -> Backend
+    {
+        self.backend
+    }
+
+    pub fn eval(&mut self, input: Expression) -> Result<Expression, EvalError>
+    {
+        match self.backend
+        {
+            Backend::TreeWalker => eval_in(input, &mut self.env),
+            Backend::Bytecode => vm::eval_in(input, &mut self.env)
+        }
+    }
+
+    /// Finds the variables in the lambda and macro bodies in `exprs` that would be unbound if they were
+    /// evaluated in order, without evaluating them
+    ///
+    /// These are only warnings, since a name could still be defined by the time it's used. Variables
+    /// outside of a body are left out, since evaluating them reports the same thing as an error
+    pub fn check(&self, exprs: &[Expression]) -> Vec<EvalError>
+    {
+        unbound(exprs, &self.env)
+    }
+
+    pub fn eval_str(&mut self, input: &str) -> Result<Vec<Expression>, Error>
+    {
+        let mut values = Vec::new();
+
+        for expr in Expression::parse_all(Token::lex(input)?)?
+        {
+            values.push(self.eval(expr)?);
+        }
+
+        Ok(values)
+    }
+
+    pub fn define<S: AsRef<str>>(&mut self, name: S, value: Expression)
+    {
+        self.env.define(name, value);
+    }
+
+    pub fn register_fn<S, A, F>(&mut self, name: S, arity: A, func: F)
+        where S: Into<String>, A: Into<Arity>, F: Fn(&[Expression]) -> Result<Expression, EvalError> + 'static
+    {
+        self.register(Native::new(name, arity.into(), NativeKind::Function, func));
+    }
+
+    pub fn register_special_form<S, A, F>(&mut self, name: S, arity: A, func: F)
+        where S: Into<String>, A: Into<Arity>, F: Fn(&[Expression]) -> Result<Expression, EvalError> + 'static
+    {
+        self.register(Native::new(name, arity.into(), NativeKind::SpecialForm, func));
+    }
+
+    fn register(&mut self, native: Native)
+    {
+        self.env.define_native(native);
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::Interpreter;
+
+    use expression::*;
+
+    #[test]
+    fn persistent_definitions()
+    {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.eval_str("(defun second (l) (car (cdr l)))").unwrap();
+        interpreter.define("x", Expression::List(List::new(vec![
+            Expression::Atom(Atom::new("a")),
+            Expression::Atom(Atom::new("b"))
+        ])));
+
+        let expected = vec![Expression::Atom(Atom::new("b"))];
+
+        let actual = interpreter.eval_str("(second x)").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn native_functions()
+    {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.register_fn("pair", 2, |args| Ok(Expression::List(List::new(args.to_vec()))));
+        interpreter.register_special_form("first-form", Arity::AtLeast(1), |args| Ok(args[0].clone()));
+
+        let expected = vec![
+            Expression::List(List::new(vec![
+                Expression::Atom(Atom::new("a")),
+                Expression::Atom(Atom::new("b"))
+            ])),
+            Expression::List(List::new(vec![
+                Expression::Atom(Atom::new("car")),
+                Expression::Atom(Atom::new("x"))
+            ])),
+            Expression::Atom(Atom::new("c"))
+        ];
+
+        let actual = interpreter.eval_str("(pair 'a 'b)
+            (first-form (car x) y)
+            ((lambda (f) (f '(c d))) car)").unwrap();
+
+        assert_eq!(expected, actual);
+        assert!(interpreter.eval_str("(pair 'a)").is_err());
+    }
+
+    #[test]
+    fn checks()
+    {
+        use token::Token;
+
+        let mut interpreter = Interpreter::new();
+
+        interpreter.eval_str("(define x 'a)").unwrap();
+        interpreter.register_special_form("first-form", 1, |args| Ok(args[0].clone()));
+
+        let exprs = Expression::parse_all(Token::lex("(defun f (l) (cons x (g l)))
+            (defun g (l) (cons y l))
+            (first-form z)
+            (cons w (lambda (&optional (a v)) a))").unwrap()).unwrap();
+
+        let unbound = interpreter.check(&exprs).iter()
+            .map(|err| format!("{}", err))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["2:32: Could not find substitution for atom 'y'", "4:43: Could not find substitution for atom 'v'"], unbound);
+    }
+
+    #[test]
+    fn error_sources()
+    {
+        use std::error::Error as StdError;
+
+        let mut interpreter = Interpreter::new();
+
+        for input in &["\"unterminated", "(car 'a", "(car 'a)"]
+        {
+            let err = interpreter.eval_str(input).unwrap_err();
+            let source = err.source().expect("every error wraps the one from the stage that failed");
+
+            assert_eq!(err.to_string(), source.to_string());
+            assert!(source.source().is_none());
+        }
+    }
+}
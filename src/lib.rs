@@ -0,0 +1,16 @@
+pub mod token;
+pub mod expression;
+mod error;
+mod eval;
+mod expand;
+mod syntax;
+mod analysis;
+mod vm;
+mod builtins;
+mod interpreter;
+
+pub use token::{Token, LexError, Position, Span};
+pub use expression::{Expression, Atom, Symbol, List, Number, Str, Native, NativeKind, Arity, ParseError};
+pub use error::{EvalError, ErrorKind};
+pub use eval::eval;
+pub use interpreter::{Interpreter, Backend, Error};
@@ -1,14 +1,7 @@
-#![allow(dead_code)]
-
 extern crate clap;
+extern crate lisp_interpreter;
 
-mod token;
-mod expression;
-mod eval;
-
-use token::Token;
-use expression::Expression;
-use eval::eval;
+use lisp_interpreter::{Token, LexError, Position, Expression, Interpreter, Backend, Error};
 
 fn main()
 {
@@ -24,20 +17,34 @@ fn main()
             .takes_value(true)
             .value_name("FILE")
             .required(false))
+        .arg(Arg::with_name("backend")
+            .short("b")
+            .long("backend")
+            .help("chooses how expressions are evaluated")
+            .takes_value(true)
+            .value_name("BACKEND")
+            .possible_values(&["tree", "bytecode"])
+            .default_value("tree"))
         .get_matches();
+
+    let backend = match matches.value_of("backend")
+    {
+        Some("bytecode") => Backend::Bytecode,
+        _ => Backend::TreeWalker
+    };
     
     if matches.is_present("file")
     {
         file(matches.value_of("file")
-            .expect("the --file option requires an input file"));
+            .expect("the --file option requires an input file"), backend);
     }
     else
     {
-        repl();
+        repl(backend);
     }
 }
 
-fn file(path: &str)
+fn file(path: &str, backend: Backend)
 {
     use std::fs::File;
     use std::io::Read;
@@ -59,44 +66,48 @@ fn file(path: &str)
         s
     };
 
-    let tokens = Token::lex(&input);
+    let tokens = match Token::lex(&input)
+    {
+        Ok(tokens) => tokens,
+        Err(err) => bad_exit(&report("err", Some(path), &input, err.into()))
+    };
 
-    match Expression::parse(tokens)
+    let exprs = match Expression::parse_all(tokens)
     {
-        Ok(expr) => match eval(expr)
+        Ok(exprs) => exprs,
+        Err(err) => bad_exit(&report("err", Some(path), &input, err.into()))
+    };
+
+    let mut interpreter = Interpreter::with_backend(backend);
+
+    for warning in interpreter.check(&exprs)
+    {
+        println!("{}", report("warn", Some(path), &input, warning.into()));
+    }
+
+    for expr in exprs
+    {
+        match interpreter.eval(expr)
         {
             Ok(val) => println!("{}", val),
-            Err(err) => println!("err: {}", err)
-        },
-        Err(err) => println!("err: {}", err)
+            Err(err) => bad_exit(&report("err", Some(path), &input, err.into()))
+        }
     }
 }
 
-fn repl()
+fn repl(backend: Backend)
 {
     use std::io::{self, Write};
 
     println!("comment 'exit' to exit");
 
-    let mut open = 0;
-    let mut tokens = Vec::new();
+    let mut session = Session::new(backend);
 
     let stdin = io::stdin();
 
     loop
     {
-        if open == 0
-        {
-            print!(">>> : ");
-        }
-        else if open > 999
-        {
-            print!("99+ : ");
-        }
-        else
-        {
-            print!("{:>03} : ", open);
-        }
+        print!("{}", session.prompt());
 
         io::stdout().flush().expect("Could not flush stdout");
 
@@ -107,33 +118,128 @@ fn repl()
             std::process::exit(0);
         }
 
-        tokens.append(&mut Token::lex(&input));
+        for output in session.read(&input)
+        {
+            println!("{}", output);
+        }
+    }
+}
+
+/// A REPL session, which keeps every input it's read so errors in code from an earlier input,
+/// like the body of a function it defined, can still be shown against that input
+struct Session
+{
+    interpreter: Interpreter,
+    /// Every complete input so far; each input is lexed as if it followed these, so spans from
+    /// any of them point into the history
+    history: String,
+    /// The lines of an input that's still missing close parentheses
+    source: String,
+    open: usize,
+}
+
+impl Session
+{
+    fn new(backend: Backend) -> Session
+    {
+        Session
+        {
+            interpreter: Interpreter::with_backend(backend),
+            history: String::new(),
+            source: String::new(),
+            open: 0,
+        }
+    }
+
+    fn prompt(&self) -> String
+    {
+        match self.open
+        {
+            0 => ">>> : ".to_owned(),
+            open if open > 999 => "99+ : ".to_owned(),
+            open => format!("{:>03} : ", open)
+        }
+    }
+
+    /// Reads a line of input, returning what to print once it completes the input
+    fn read(&mut self, input: &str) -> Vec<String>
+    {
+        self.source.push_str(input);
+
+        let start = Position::new(self.history.lines().count() + 1, 1);
 
-        open = match Token::count_parens(&tokens)
+        let tokens = match Token::lex_from(&self.source, start)
         {
-            Some(o) => o,
-            None =>
+            Ok(tokens) => tokens,
+            Err(LexError::UnterminatedString(_)) => return Vec::new(),
+            Err(err) =>
             {
-                println!("err : Too many close close parenthesis");
-                tokens.clear();
-                0
+                self.finish();
+                return vec![report("err", None, &self.history, err.into())];
             }
         };
 
-        if open == 0 && tokens.len() != 0
+        self.open = Token::count_parens(&tokens).unwrap_or(0);
+
+        if self.open != 0
         {
-            match Expression::parse(tokens.clone())
+            return Vec::new();
+        }
+
+        self.finish();
+
+        let mut output = Vec::new();
+
+        match Expression::parse_all(tokens)
+        {
+            Ok(exprs) =>
             {
-                Ok(expr) => match eval(expr)
+                for warning in self.interpreter.check(&exprs)
                 {
-                    Ok(res) => println!("<<< : {}", res),
-                    Err(err) => println!("err : {}", err)
-                },
-                Err(err) => println!("err : {}", err)
-            }
+                    output.push(report("warn", None, &self.history, warning.into()));
+                }
 
-            tokens.clear();
+                for expr in exprs
+                {
+                    match self.interpreter.eval(expr)
+                    {
+                        Ok(res) => output.push(format!("<<< : {}", res)),
+                        Err(err) =>
+                        {
+                            output.push(report("err", None, &self.history, err.into()));
+                            break;
+                        }
+                    }
+                }
+            },
+            Err(err) => output.push(report("err", None, &self.history, err.into()))
         }
+
+        output
+    }
+
+    /// Moves the current input into the history
+    fn finish(&mut self)
+    {
+        self.history.push_str(&self.source);
+        self.source.clear();
+        self.open = 0;
+    }
+}
+
+fn report(level: &str, file: Option<&str>, source: &str, err: Error) -> String
+{
+    let message = match (file, err.span())
+    {
+        (Some(file), Some(_)) => format!("{}: {}:{}", level, file, err),
+        (Some(file), None) => format!("{}: {}: {}", level, file, err),
+        (None, _) => format!("{} : {}", level, err)
+    };
+
+    match err.span()
+    {
+        Some(span) => format!("{}\n{}", message, span.snippet(source)),
+        None => message
     }
 }
 
@@ -152,4 +258,31 @@ fn bad_exit(msg: &str) -> !
 {
     println!("{}", msg);
     ::std::process::exit(1);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn earlier_inputs()
+    {
+        let mut session = Session::new(Backend::TreeWalker);
+
+        assert!(session.read("(defun f (x)\n").is_empty());
+        assert_eq!(vec!["<<< : f"], session.read("  (car x))\n"));
+        assert_eq!(vec!["<<< : 1"], session.read("(+ 0 1)\n"));
+
+        let output = session.read("(f 1)\n");
+
+        assert_eq!(1, output.len());
+        assert!(output[0].starts_with("err : 2:3: "), "{}", output[0]);
+        assert!(output[0].ends_with("2 |   (car x))\n  |   ^^^^^^^"), "{}", output[0]);
+
+        // an unbound name at the top level is an error when it's evaluated, without a warning first
+        let output = session.read("(cons unbound 1)\n");
+
+        assert_eq!(1, output.len());
+        assert!(output[0].starts_with("err : 5:7: "), "{}", output[0]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Pattern based macros, defined with `define-syntax` and `syntax-rules`
+//!
+//! A call is matched against each rule's pattern in turn, and replaced with the template of the
+//! first one that matches. Identifiers the template introduces are renamed to fresh aliases on
+//! every expansion; the analysis resolves an alias to a binding the expansion made, and otherwise
+//! to whatever the original name means, so neither the macro nor the code using it can capture
+//! the other's variables
+
+use std::collections::HashMap;
+
+use expression::*;
+use error::EvalError;
+
+#[derive(Debug)]
+pub struct Rules
+{
+    name: Atom,
+    literals: Vec<Symbol>,
+    /// Each pattern, without the macro's name, and it's template
+    rules: Vec<(Expression, Expression)>,
+}
+
+#[derive(Clone)]
+enum Binding
+{
+    One(Expression),
+    /// What a variable matched in each repetition of a pattern followed by an ellipsis
+    Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<Symbol, Binding>;
+
+impl Rules
+{
+    /// Reads the `(syntax-rules (literal ...) (pattern template) ...)` form of a `define-syntax`
+    pub fn parse(name: Atom, spec: &Expression) -> Result<Rules, EvalError>
+    {
+        let malformed = || EvalError::syntax(format!("{} needs a (syntax-rules (literal ...) (pattern template) ...) form", name));
+
+        let mut parts = match *spec
+        {
+            Expression::List(ref spec) if spec.is_proper() && matches!(spec.car(), Some(&Expression::Atom(head)) if head.symbol().root() == Symbol::SYNTAX_RULES) => spec.iter().skip(1),
+            _ => return Err(malformed())
+        };
+
+        let literals = match parts.next()
+        {
+            Some(Expression::List(literals)) if literals.is_proper() => literals.iter()
+                .map(|literal| match *literal
+                {
+                    Expression::Atom(atom) => Ok(atom.symbol()),
+                    _ => Err(malformed())
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(malformed())
+        };
+
+        let mut rules = Vec::new();
+
+        for rule in parts
+        {
+            let (pattern, template) = match *rule
+            {
+                Expression::List(ref rule) if rule.is_proper() && rule.len() == 2 =>
+                {
+                    let mut parts = rule.iter();
+                    (parts.next().expect("the rule has two parts"), parts.next().expect("the rule has two parts"))
+                },
+                _ => return Err(malformed())
+            };
+
+            // the first element stands for the macro's name, which isn't matched
+            let pattern = match *pattern
+            {
+                Expression::List(ref pattern) if !pattern.is_empty() => pattern.cdr().expect("the pattern isn't empty").clone(),
+                _ => return Err(malformed())
+            };
+
+            check(&pattern, name)?;
+            rules.push((pattern, template.clone()));
+        }
+
+        Ok(Rules
+        {
+            name,
+            literals,
+            rules,
+        })
+    }
+
+    /// The code a call to this macro expands to
+    pub fn expand(&self, call: &List) -> Result<Expression, EvalError>
+    {
+        let arguments = call.cdr().expect("a call has the macro's name");
+
+        for (pattern, template) in self.rules.iter()
+        {
+            let mut bindings = HashMap::new();
+
+            if self.matches(pattern, arguments, &mut bindings)
+            {
+                return self.instantiate(template, &bindings, &mut HashMap::new());
+            }
+        }
+
+        Err(EvalError::syntax(format!("no rule of {} matches {}", self.name, Expression::List(call.clone()))))
+    }
+
+    fn matches(&self, pattern: &Expression, input: &Expression, bindings: &mut Bindings) -> bool
+    {
+        let pattern = match *pattern
+        {
+            Expression::Atom(atom) if atom.symbol() == Symbol::WILDCARD => return true,
+            Expression::Atom(atom) if self.literals.contains(&atom.symbol()) => return matches!(*input, Expression::Atom(input) if input.symbol().root() == atom.symbol().root()),
+            Expression::Atom(atom) =>
+            {
+                bindings.insert(atom.symbol(), Binding::One(input.clone()));
+                return true;
+            },
+            Expression::List(ref pattern) if !pattern.is_empty() => pattern,
+            _ => return *pattern == *input
+        };
+
+        let input = match *input
+        {
+            Expression::List(ref input) => input,
+            _ => return false
+        };
+
+        let items = pattern.iter().collect::<Vec<_>>();
+        let inputs = input.iter().collect::<Vec<_>>();
+
+        let (before, repeated, after) = match items.iter().position(|item| is_ellipsis(item))
+        {
+            Some(ellipsis) => (&items[..ellipsis - 1], Some(items[ellipsis - 1]), &items[ellipsis + 1..]),
+            None => (&items[..], None, &items[..0])
+        };
+
+        let fixed = before.len() + after.len();
+
+        // without a dotted tail in the pattern, the input has to be a proper list of the right length
+        let count = match (repeated, pattern.tail())
+        {
+            _ if inputs.len() < fixed => return false,
+            (_, None) if input.tail().is_some() => return false,
+            (None, None) if inputs.len() != fixed => return false,
+            (Some(_), _) => inputs.len() - fixed,
+            (None, _) => 0
+        };
+
+        let middle = &inputs[before.len()..before.len() + count];
+        let (rest, remainder) = inputs[before.len() + count..].split_at(after.len());
+
+        if !before.iter().zip(inputs.iter()).all(|(pattern, input)| self.matches(pattern, input, bindings))
+        {
+            return false;
+        }
+
+        if let Some(repeated) = repeated
+        {
+            let mut matched = Vec::new();
+
+            for input in middle
+            {
+                let mut inner = HashMap::new();
+
+                if !self.matches(repeated, input, &mut inner)
+                {
+                    return false;
+                }
+
+                matched.push(inner);
+            }
+
+            for variable in self.variables(repeated)
+            {
+                let values = matched.iter_mut()
+                    .map(|inner| inner.remove(&variable).expect("every match binds the pattern's variables"))
+                    .collect();
+
+                bindings.insert(variable, Binding::Many(values));
+            }
+        }
+
+        if !after.iter().zip(rest.iter()).all(|(pattern, input)| self.matches(pattern, input, bindings))
+        {
+            return false;
+        }
+
+        match pattern.tail()
+        {
+            Some(tail) =>
+            {
+                let remainder = remainder.iter().map(|&item| item.clone()).collect();
+                self.matches(tail, &list(remainder, input.tail().cloned().unwrap_or_else(nil)), bindings)
+            },
+            None => true
+        }
+    }
+
+    /// The pattern variables in `pattern`
+    fn variables(&self, pattern: &Expression) -> Vec<Symbol>
+    {
+        let mut variables = Vec::new();
+
+        atoms(pattern, &mut variables);
+        variables.retain(|&symbol| !matches!(symbol, Symbol::ELLIPSIS | Symbol::WILDCARD) && !self.literals.contains(&symbol));
+
+        variables
+    }
+
+    fn instantiate(&self, template: &Expression, bindings: &Bindings, renames: &mut HashMap<Symbol, Symbol>) -> Result<Expression, EvalError>
+    {
+        let template = match *template
+        {
+            Expression::Atom(atom) => return match bindings.get(&atom.symbol())
+            {
+                Some(Binding::One(value)) => Ok(value.clone()),
+                Some(Binding::Many(_)) => Err(EvalError::syntax(format!("{} needs an ellipsis after it in the template of {}", atom, self.name))),
+                // constants can't be bound, so they don't need renaming
+                None if matches!(atom.symbol(), Symbol::T | Symbol::NIL) || atom.is_keyword() => Ok(Expression::Atom(atom)),
+                None =>
+                {
+                    let alias = *renames.entry(atom.symbol()).or_insert_with(|| atom.symbol().alias());
+                    Ok(Expression::Atom(atom.renamed(alias)))
+                }
+            },
+            Expression::List(ref template) if !template.is_empty() => template,
+            ref other => return Ok(other.clone())
+        };
+
+        let items = template.iter().collect::<Vec<_>>();
+        let mut built = Vec::new();
+        let mut index = 0;
+
+        while index < items.len()
+        {
+            if items.get(index + 1).is_some_and(|next| is_ellipsis(next))
+            {
+                built.extend(self.repeat(items[index], bindings, renames)?);
+                index += 2;
+            }
+            else
+            {
+                built.push(self.instantiate(items[index], bindings, renames)?);
+                index += 1;
+            }
+        }
+
+        let tail = match template.tail()
+        {
+            Some(tail) => self.instantiate(tail, bindings, renames)?,
+            None => nil()
+        };
+
+        Ok(list(built, tail))
+    }
+
+    /// Instantiates a template followed by an ellipsis once for each form it's variables matched
+    fn repeat(&self, template: &Expression, bindings: &Bindings, renames: &mut HashMap<Symbol, Symbol>) -> Result<Vec<Expression>, EvalError>
+    {
+        let mut variables = Vec::new();
+        atoms(template, &mut variables);
+
+        let repeated = variables.iter()
+            .filter_map(|variable| match bindings.get(variable)
+            {
+                Some(Binding::Many(values)) => Some((*variable, values)),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+
+        let count = match repeated.first()
+        {
+            Some(&(_, values)) => values.len(),
+            None => return Err(EvalError::syntax(format!("an ellipsis in the template of {} follows a template without a pattern variable that can repeat", self.name)))
+        };
+
+        if repeated.iter().any(|&(_, values)| values.len() != count)
+        {
+            return Err(EvalError::syntax(format!("the pattern variables before an ellipsis in the template of {} matched different numbers of forms", self.name)));
+        }
+
+        (0..count).map(|index|
+        {
+            let mut inner = bindings.clone();
+
+            for &(variable, values) in repeated.iter()
+            {
+                inner.insert(variable, values[index].clone());
+            }
+
+            self.instantiate(template, &inner, renames)
+        }).collect()
+    }
+}
+
+/// Checks that each list in a pattern has at most one ellipsis, after a subpattern
+fn check(pattern: &Expression, name: Atom) -> Result<(), EvalError>
+{
+    if let Expression::List(ref pattern) = *pattern
+    {
+        let items = pattern.iter().collect::<Vec<_>>();
+        let ellipses = items.iter().enumerate().filter(|&(_, item)| is_ellipsis(item)).map(|(index, _)| index).collect::<Vec<_>>();
+
+        if ellipses.len() > 1 || ellipses.first() == Some(&0)
+        {
+            return Err(EvalError::syntax(format!("each list in a pattern of {} can only have one ellipsis, after a subpattern", name)));
+        }
+
+        for item in items.iter().chain(pattern.tail().iter())
+        {
+            check(item, name)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_ellipsis(expr: &Expression) -> bool
+{
+    matches!(*expr, Expression::Atom(atom) if atom.symbol() == Symbol::ELLIPSIS)
+}
+
+/// Collects the symbols of every atom in `expr`
+fn atoms(expr: &Expression, found: &mut Vec<Symbol>)
+{
+    match *expr
+    {
+        Expression::Atom(atom) if !found.contains(&atom.symbol()) => found.push(atom.symbol()),
+        Expression::List(ref list) =>
+        {
+            for item in list.iter().chain(list.tail())
+            {
+                atoms(item, found);
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Builds `(items ... . tail)`, which is just `tail` if there are no items
+fn list(items: Vec<Expression>, tail: Expression) -> Expression
+{
+    match tail
+    {
+        Expression::List(_) => Expression::List(List::dotted(items, tail)),
+        tail if items.is_empty() => tail,
+        tail => Expression::List(List::dotted(items, tail))
+    }
+}
+
+fn nil() -> Expression
+{
+    Expression::List(List::nil())
+}
+
+/// Undoes the renaming of identifiers in quoted data, which should read as it was written
+pub fn strip(expr: &Expression) -> Expression
+{
+    match *expr
+    {
+        Expression::Atom(atom) => Expression::Atom(atom.root()),
+        Expression::List(ref list) if renamed(expr) =>
+        {
+            let stripped = List::dotted(list.iter().map(strip).collect(), list.tail().map(strip).unwrap_or_else(nil));
+
+            Expression::List(match list.span()
+            {
+                Some(span) => stripped.with_span(span),
+                None => stripped
+            })
+        },
+        ref other => other.clone()
+    }
+}
+
+fn renamed(expr: &Expression) -> bool
+{
+    match *expr
+    {
+        Expression::Atom(atom) => atom.symbol().original().is_some(),
+        Expression::List(ref list) => list.iter().chain(list.tail()).any(renamed),
+        _ => false
+    }
+}
@@ -1,24 +1,191 @@
+use std::str::Chars;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token
 {
     Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
     OpenParen,
     CloseParen,
     Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    Dot,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position
+{
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Span
+{
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Position
+{
+    pub fn new(line: usize, col: usize) -> Position
+    {
+        Position
+        {
+            line,
+            col,
+        }
+    }
+}
+
+impl Span
+{
+    pub fn new(start: Position, end: Position) -> Span
+    {
+        Span
+        {
+            start,
+            end,
+        }
+    }
+
+    pub fn snippet(&self, source: &str) -> String
+    {
+        let line = source.lines().nth(self.start.line - 1).unwrap_or("");
+        let length = line.chars().count();
+
+        let width = if self.end.line == self.start.line
+        {
+            self.end.col.saturating_sub(self.start.col)
+        }
+        else
+        {
+            (length + 1).saturating_sub(self.start.col)
+        };
+
+        let indent = line.chars()
+            .take(self.start.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        let gutter = format!("{}", self.start.line).len();
+
+        format!("{:>w$} | {}\n{:>w$} | {}{}", self.start.line, line, "", indent, "^".repeat(width.max(1)), w = gutter)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexError
+{
+    UnterminatedString(Position),
+    InvalidEscape(String, Position),
+}
+
+impl LexError
+{
+    pub fn position(&self) -> Position
+    {
+        match *self
+        {
+            LexError::UnterminatedString(position) => position,
+            LexError::InvalidEscape(_, position) => position
+        }
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for Position
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+impl fmt::Display for LexError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            LexError::UnterminatedString(position) => write!(f, "{}: Unterminated string", position),
+            LexError::InvalidEscape(ref escape, position) => write!(f, "{}: Invalid escape sequence '\\{}'", position, escape)
+        }
+    }
+}
+
+impl ::std::error::Error for LexError {}
+
+struct Cursor<'a>
+{
+    chars: Chars<'a>,
+    position: Position,
+}
+
+impl<'a> Cursor<'a>
+{
+    fn next(&mut self) -> Option<char>
+    {
+        let c = self.chars.next()?;
+
+        if c == '\n'
+        {
+            self.position.line += 1;
+            self.position.col = 1;
+        }
+        else
+        {
+            self.position.col += 1;
+        }
+
+        Some(c)
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.chars.clone().next()
+    }
 }
 
 impl Token
 {
-    pub fn lex(s: &str) -> Vec<Token>
+    pub fn lex(s: &str) -> Result<Vec<(Token, Span)>, LexError>
+    {
+        Token::lex_from(s, Position::new(1, 1))
+    }
+
+    /// Lexes `s` as if it started at `start`, for input that continues some earlier source, so
+    /// the spans point into the source as a whole
+    pub fn lex_from(s: &str, start: Position) -> Result<Vec<(Token, Span)>, LexError>
     {
         let mut tokens = Vec::new();
 
         let mut in_comment = false;
         let mut in_ident = false;
         let mut ident = String::new();
+        let mut ident_start = start;
+
+        let mut cursor = Cursor
+        {
+            chars: s.chars(),
+            position: start,
+        };
 
-        for c in s.chars()
+        loop
         {
+            let start = cursor.position;
+
+            let c = match cursor.next()
+            {
+                Some(c) => c,
+                None => break
+            };
+
             if in_comment
             {
                 if c == '\n'
@@ -30,12 +197,12 @@ impl Token
             {
                 match c
                 {
-                    ';' | '(' | ')' | '\'' |
+                    ';' | '(' | ')' | '\'' | '`' | ',' | '"' |
                     ' ' | '\t' | '\r' | '\n' =>
                     {
                         if in_ident
                         {
-                            tokens.push(Token::Ident(ident.clone()));
+                            tokens.push((Token::word(&ident), Span::new(ident_start, start)));
                             ident.clear();
                             in_ident = false;
                         }
@@ -43,14 +210,31 @@ impl Token
                         match c
                         {
                             ';' => in_comment = true,
-                            '(' => tokens.push(Token::OpenParen),
-                            ')' => tokens.push(Token::CloseParen),
-                            '\'' => tokens.push(Token::Quote),
+                            '(' => tokens.push((Token::OpenParen, Span::new(start, cursor.position))),
+                            ')' => tokens.push((Token::CloseParen, Span::new(start, cursor.position))),
+                            '\'' => tokens.push((Token::Quote, Span::new(start, cursor.position))),
+                            '`' => tokens.push((Token::Quasiquote, Span::new(start, cursor.position))),
+                            ',' if cursor.peek() == Some('@') =>
+                            {
+                                cursor.next();
+                                tokens.push((Token::UnquoteSplicing, Span::new(start, cursor.position)));
+                            },
+                            ',' => tokens.push((Token::Unquote, Span::new(start, cursor.position))),
+                            '"' =>
+                            {
+                                let string = Token::string(&mut cursor, start)?;
+                                tokens.push((Token::Str(string), Span::new(start, cursor.position)));
+                            },
                             _ => {}
                         }
                     },
                     c =>
                     {
+                        if !in_ident
+                        {
+                            ident_start = start;
+                        }
+
                         in_ident = true;
                         ident.push(c);
                     }
@@ -60,22 +244,105 @@ impl Token
 
         if in_ident
         {
-            tokens.push(Token::Ident(ident));
+            tokens.push((Token::word(&ident), Span::new(ident_start, cursor.position)));
         }
 
-        tokens
+        Ok(tokens)
     }
 
-    pub fn count_parens(tokens: &[Token]) -> Option<usize>
+    fn string(cursor: &mut Cursor, start: Position) -> Result<String, LexError>
+    {
+        let mut string = String::new();
+
+        loop
+        {
+            let escape_start = cursor.position;
+
+            match cursor.next()
+            {
+                Some('"') => return Ok(string),
+                Some('\\') => match cursor.next()
+                {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('0') => string.push('\0'),
+                    Some('\\') => string.push('\\'),
+                    Some('"') => string.push('"'),
+                    Some('u') =>
+                    {
+                        let mut escape = "u".to_owned();
+
+                        if cursor.next() != Some('{')
+                        {
+                            return Err(LexError::InvalidEscape(escape, escape_start));
+                        }
+
+                        escape.push('{');
+
+                        loop
+                        {
+                            match cursor.next()
+                            {
+                                Some('}') => break,
+                                Some(c) => escape.push(c),
+                                None => return Err(LexError::UnterminatedString(start))
+                            }
+                        }
+
+                        escape.push('}');
+
+                        match u32::from_str_radix(&escape[2..escape.len() - 1], 16).ok().and_then(::std::char::from_u32)
+                        {
+                            Some(c) => string.push(c),
+                            None => return Err(LexError::InvalidEscape(escape, escape_start))
+                        }
+                    },
+                    Some(c) => return Err(LexError::InvalidEscape(c.to_string(), escape_start)),
+                    None => return Err(LexError::UnterminatedString(start))
+                },
+                Some(c) => string.push(c),
+                None => return Err(LexError::UnterminatedString(start))
+            }
+        }
+    }
+
+    fn word(s: &str) -> Token
+    {
+        if s == "."
+        {
+            return Token::Dot;
+        }
+
+        let unsigned = s.strip_prefix('+').or_else(|| s.strip_prefix('-')).unwrap_or(s);
+        let digits = unsigned.strip_prefix('.').unwrap_or(unsigned);
+
+        if digits.starts_with(|c: char| c.is_ascii_digit())
+        {
+            if let Ok(i) = s.parse::<i64>()
+            {
+                return Token::Int(i);
+            }
+
+            if let Ok(f) = s.parse::<f64>()
+            {
+                return Token::Float(f);
+            }
+        }
+
+        Token::Ident(s.to_owned())
+    }
+
+    pub fn count_parens(tokens: &[(Token, Span)]) -> Option<usize>
     {
         let mut open = 0;
 
-        for token in tokens.iter()
+        for (token, _) in tokens.iter()
         {
-            match token
+            match *token
             {
-                &Token::OpenParen => open += 1,
-                &Token::CloseParen => if open == 0
+                Token::OpenParen => open += 1,
+                Token::CloseParen => if open == 0
                 {
                     return None
                 }
@@ -94,7 +361,12 @@ impl Token
 #[cfg(test)]
 mod test
 {
-    use super::Token;
+    use super::{Token, LexError, Position, Span};
+
+    fn tokens(input: &str) -> Vec<Token>
+    {
+        Token::lex(input).unwrap().into_iter().map(|(token, _)| token).collect()
+    }
 
     #[test]
     fn lexing()
@@ -148,8 +420,142 @@ mod test
             Token::CloseParen,
         ];
 
-        let actual = Token::lex(input);
+        let actual = tokens(input);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn numbers()
+    {
+        let input = "(+ 1 -2 3.5 .5 -1e3 99999999999999999999 - 1+ 2x)";
+
+        let expected = vec![
+            Token::OpenParen,
+                Token::Ident("+".to_owned()),
+                Token::Int(1),
+                Token::Int(-2),
+                Token::Float(3.5),
+                Token::Float(0.5),
+                Token::Float(-1000.0),
+                Token::Float(99999999999999999999.0),
+                Token::Ident("-".to_owned()),
+                Token::Ident("1+".to_owned()),
+                Token::Ident("2x".to_owned()),
+            Token::CloseParen,
+        ];
+
+        let actual = tokens(input);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn dots()
+    {
+        let input = "(a . (b . .5)) ...";
+
+        let expected = vec![
+            Token::OpenParen,
+                Token::Ident("a".to_owned()),
+                Token::Dot,
+                Token::OpenParen,
+                    Token::Ident("b".to_owned()),
+                    Token::Dot,
+                    Token::Float(0.5),
+                Token::CloseParen,
+            Token::CloseParen,
+            Token::Ident("...".to_owned()),
+        ];
+
+        let actual = tokens(input);
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn quasiquotes()
+    {
+        let input = "`(a ,b ,@c, d),@e";
+
+        let expected = vec![
+            Token::Quasiquote,
+            Token::OpenParen,
+                Token::Ident("a".to_owned()),
+                Token::Unquote,
+                Token::Ident("b".to_owned()),
+                Token::UnquoteSplicing,
+                Token::Ident("c".to_owned()),
+                Token::Unquote,
+                Token::Ident("d".to_owned()),
+            Token::CloseParen,
+            Token::UnquoteSplicing,
+            Token::Ident("e".to_owned()),
+        ];
+
+        let actual = tokens(input);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn strings()
+    {
+        let input = "(f \"a (b) ; c\"\"\\\"q\\\" \\\\ \\n \\u{3bb}\"x)";
+
+        let expected = vec![
+            Token::OpenParen,
+                Token::Ident("f".to_owned()),
+                Token::Str("a (b) ; c".to_owned()),
+                Token::Str("\"q\" \\ \n \u{3bb}".to_owned()),
+                Token::Ident("x".to_owned()),
+            Token::CloseParen,
+        ];
+
+        let actual = tokens(input);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn string_errors()
+    {
+        assert_eq!(Err(LexError::UnterminatedString(Position::new(1, 4))), Token::lex("(f \"abc)"));
+        assert_eq!(Err(LexError::InvalidEscape("q".to_owned(), Position::new(2, 3))), Token::lex("\n \"\\q\""));
+        assert_eq!(Err(LexError::InvalidEscape("u{zz}".to_owned(), Position::new(1, 2))), Token::lex("\"\\u{zz}\""));
+    }
+
+    #[test]
+    fn snippets()
+    {
+        let source = "(a b)\n  (car\n    x)";
+
+        assert_eq!("2 |   (car\n  |   ^^^^", Span::new(Position::new(2, 3), Position::new(3, 7)).snippet(source));
+        assert_eq!("1 | (a b)\n  |    ^", Span::new(Position::new(1, 4), Position::new(1, 5)).snippet(source));
+        assert_eq!("1 | (a b)\n  |      ^", Span::new(Position::new(1, 6), Position::new(1, 6)).snippet(source));
+    }
+
+    #[test]
+    fn positions()
+    {
+        let input = "(car ; comment
+  'abc \"s\")";
+
+        let expected = vec![
+            (Token::OpenParen, Span::new(Position::new(1, 1), Position::new(1, 2))),
+            (Token::Ident("car".to_owned()), Span::new(Position::new(1, 2), Position::new(1, 5))),
+            (Token::Quote, Span::new(Position::new(2, 3), Position::new(2, 4))),
+            (Token::Ident("abc".to_owned()), Span::new(Position::new(2, 4), Position::new(2, 7))),
+            (Token::Str("s".to_owned()), Span::new(Position::new(2, 8), Position::new(2, 11))),
+            (Token::CloseParen, Span::new(Position::new(2, 11), Position::new(2, 12))),
+        ];
+
+        let actual = Token::lex(input).unwrap();
+
+        assert_eq!(expected, actual);
+
+        let continued = Token::lex_from("(a\n b)", Position::new(4, 1)).unwrap();
+
+        assert_eq!(Span::new(Position::new(5, 2), Position::new(5, 3)), continued[2].1);
+    }
 }
\ No newline at end of file
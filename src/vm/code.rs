@@ -0,0 +1,79 @@
+use std::rc::Rc;
+
+use expression::{Expression, Atom};
+use error::EvalError;
+use analysis::{Address, Lambda};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Op
+{
+    /// Pushes `constants[i]`
+    Const(usize),
+    /// Pushes a local variable from the current frame's scope
+    Local(Atom, Address),
+    /// Pushes a global
+    Global(Atom),
+    /// Pushes a new closure for `lambdas[i]`, capturing the current frame's scope
+    Lambda(usize),
+    /// Replaces the closure on top of the stack with one labelled with the atom
+    Label(Atom),
+    /// Pops a value into a global, and pushes it's name
+    Define(Atom),
+    /// Sets the macro expander for the atom to a new closure for `lambdas[i]`, and pushes the atom
+    Defmacro(Atom, usize),
+    /// Pops a form, and pushes what it expands to, either once or until it's no longer a macro call
+    Macroexpand(bool),
+    /// Pops `n` values into a new frame of local variables
+    Bind(usize),
+    /// Adds a new frame of `n` empty local variables
+    Reserve(usize),
+    /// Pops a value into slot `i` of the innermost frame of local variables
+    Store(usize),
+    /// Drops the innermost frame of local variables
+    Unbind,
+    /// Jumps if slot `i` of the innermost frame of local variables has been filled, skipping the default for a parameter that was passed
+    JumpIfBound(usize, usize),
+    Jump(usize),
+    /// Pops a value, and jumps if it's a list, which tests treat as false
+    JumpIfList(usize),
+    /// Jumps if the value on top of the stack is a list, leaving it there, and pops it otherwise
+    BranchIfList(usize),
+    /// Jumps if the value on top of the stack isn't a list, leaving it there, and pops it otherwise
+    BranchUnlessList(usize),
+    Pop,
+    /// Pops a cdr and then a car, and pushes the pair of them
+    Cons,
+    /// Pops a value and then a list, and pushes a copy of the list ending in the value
+    Append,
+    /// Checks the function on top of the stack before it's `n` arguments are pushed,
+    /// jumping to the code that pushes them unevaluated if it's a macro or a special form
+    Prepare(usize, usize),
+    /// Pops `n` arguments and the function below them, and calls it
+    Call(usize),
+    /// Like `Call`, but the callee replaces the current frame
+    TailCall(usize),
+    /// Pops the current frame, passing the value on top of the stack back to the caller
+    Return,
+    /// Fails with `errors[i]`
+    Raise(usize),
+}
+
+/// The compiled form of a closure body or top level expression
+pub struct Code
+{
+    pub ops: Vec<Op>,
+    pub constants: Vec<Expression>,
+    pub lambdas: Vec<Rc<Lambda>>,
+    pub errors: Vec<EvalError>,
+    /// The innermost expression each op was compiled from, as an index into `forms`
+    pub origins: Vec<usize>,
+    pub forms: Vec<Expression>,
+}
+
+impl Code
+{
+    pub fn form(&self, pc: usize) -> &Expression
+    {
+        &self.forms[self.origins[pc]]
+    }
+}
@@ -0,0 +1,362 @@
+use std::rc::Rc;
+
+use expression::*;
+use error::EvalError;
+use analysis::{Node, NodeKind, Lambda};
+
+use super::code::{Code, Op};
+
+/// Compiles an analysed closure body or top level expression
+///
+/// Mistakes like a malformed `cond` don't stop compilation; the analysis leaves them as nodes that
+/// compile to an op raising the error, so it's only reported if evaluation reaches it, as the
+/// tree-walker would
+pub fn compile(body: &Node) -> Code
+{
+    let mut compiler = Compiler
+    {
+        code: Code
+        {
+            ops: Vec::new(),
+            constants: Vec::new(),
+            lambdas: Vec::new(),
+            errors: Vec::new(),
+            origins: Vec::new(),
+            forms: Vec::new(),
+        },
+        form: 0,
+    };
+
+    compiler.node(body, true);
+
+    compiler.code
+}
+
+struct Compiler
+{
+    code: Code,
+    form: usize,
+}
+
+impl Compiler
+{
+    fn emit(&mut self, op: Op) -> usize
+    {
+        self.code.ops.push(op);
+        self.code.origins.push(self.form);
+        self.code.ops.len() - 1
+    }
+
+    fn here(&self) -> usize
+    {
+        self.code.ops.len()
+    }
+
+    fn patch(&mut self, at: usize)
+    {
+        let target = self.here();
+
+        match self.code.ops[at]
+        {
+            Op::Jump(ref mut to) | Op::JumpIfBound(_, ref mut to) | Op::JumpIfList(ref mut to) | Op::BranchIfList(ref mut to) | Op::BranchUnlessList(ref mut to) | Op::Prepare(_, ref mut to) => *to = target,
+            ref other => unreachable!("{:?} has no jump target to patch", other)
+        }
+    }
+
+    fn constant(&mut self, value: Expression)
+    {
+        let index = self.code.constants.len();
+        self.code.constants.push(value);
+        self.emit(Op::Const(index));
+    }
+
+    fn lambda(&mut self, lambda: &Rc<Lambda>) -> usize
+    {
+        self.code.lambdas.push(lambda.clone());
+        self.code.lambdas.len() - 1
+    }
+
+    fn raise(&mut self, err: EvalError)
+    {
+        let index = self.code.errors.len();
+        self.code.errors.push(err);
+        self.emit(Op::Raise(index));
+    }
+
+    fn finish(&mut self, tail: bool)
+    {
+        if tail
+        {
+            self.emit(Op::Return);
+        }
+    }
+
+    fn node(&mut self, node: &Node, tail: bool)
+    {
+        let outer = self.form;
+
+        self.code.forms.push(node.form().clone());
+        self.form = self.code.forms.len() - 1;
+
+        match *node.kind()
+        {
+            NodeKind::Constant(ref value) => self.constant(value.clone()),
+            NodeKind::Local(atom, address) =>
+            {
+                self.emit(Op::Local(atom, address));
+            },
+            NodeKind::Global(atom) =>
+            {
+                self.emit(Op::Global(atom));
+            },
+            NodeKind::Cond(ref clauses, ref malformed) => self.cond(clauses, malformed, tail),
+            NodeKind::If(ref test, ref then, ref otherwise) =>
+            {
+                self.node(test, false);
+
+                let next = self.emit(Op::JumpIfList(0));
+
+                self.node(then, tail);
+
+                let end = if tail { None } else { Some(self.emit(Op::Jump(0))) };
+
+                self.patch(next);
+
+                match *otherwise
+                {
+                    Some(ref otherwise) => self.node(otherwise, tail),
+                    None =>
+                    {
+                        self.constant(Expression::List(List::nil()));
+                        self.finish(tail);
+                    }
+                }
+
+                if let Some(end) = end
+                {
+                    self.patch(end);
+                }
+            },
+            NodeKind::And(ref parts) => self.sequence(parts, Expression::Atom(Atom::from(Symbol::T)), Some(Op::BranchIfList(0)), tail),
+            NodeKind::Or(ref parts) => self.sequence(parts, Expression::List(List::nil()), Some(Op::BranchUnlessList(0)), tail),
+            NodeKind::Progn(ref parts) => self.sequence(parts, Expression::List(List::nil()), None, tail),
+            NodeKind::Lambda(ref lambda) =>
+            {
+                let index = self.lambda(lambda);
+                self.emit(Op::Lambda(index));
+            },
+            NodeKind::Label(name, ref value) =>
+            {
+                self.node(value, false);
+                self.emit(Op::Label(name));
+            },
+            NodeKind::Define(name, ref value) =>
+            {
+                self.node(value, false);
+                self.emit(Op::Define(name));
+            },
+            NodeKind::Defmacro(name, ref lambda) =>
+            {
+                let index = self.lambda(lambda);
+                self.emit(Op::Defmacro(name, index));
+            },
+            NodeKind::Macroexpand(ref form, all) =>
+            {
+                self.node(form, false);
+                self.emit(Op::Macroexpand(all));
+            },
+            NodeKind::Let(ref values, ref body) =>
+            {
+                for value in values
+                {
+                    self.node(value, false);
+                }
+
+                self.emit(Op::Bind(values.len()));
+                self.scoped(body, tail);
+            },
+            NodeKind::Letrec(ref values, ref body) =>
+            {
+                self.emit(Op::Reserve(values.len()));
+
+                for (index, value) in values.iter().enumerate()
+                {
+                    self.node(value, false);
+                    self.emit(Op::Store(index));
+                }
+
+                self.scoped(body, tail);
+            },
+            NodeKind::Cons(ref car, ref cdr) =>
+            {
+                self.node(car, false);
+                self.node(cdr, false);
+                self.emit(Op::Cons);
+            },
+            NodeKind::Append(ref list, ref rest) =>
+            {
+                self.node(list, false);
+                self.node(rest, false);
+                self.emit(Op::Append);
+            },
+            NodeKind::Call(ref function, ref arguments) => self.call(node, function, arguments, tail),
+            NodeKind::Defaults(ref defaults, ref body) =>
+            {
+                for (index, default) in defaults
+                {
+                    let skip = self.emit(Op::JumpIfBound(*index, 0));
+
+                    self.node(default, false);
+                    self.emit(Op::Store(*index));
+                    self.patch(skip);
+                }
+
+                self.node(body, tail);
+            },
+            NodeKind::Error(ref err) => self.raise(err.clone())
+        }
+
+        // these handle their own tail position
+        if !matches!(*node.kind(), NodeKind::Cond(..) | NodeKind::If(..) | NodeKind::And(_) | NodeKind::Or(_) | NodeKind::Progn(_) | NodeKind::Let(..) | NodeKind::Letrec(..) | NodeKind::Call(..) | NodeKind::Defaults(..) | NodeKind::Error(_))
+        {
+            self.finish(tail);
+        }
+
+        self.form = outer;
+    }
+
+    /// The body of a `let` like form, which drops it's frame afterwards unless it returns
+    fn scoped(&mut self, body: &Node, tail: bool)
+    {
+        self.node(body, tail);
+
+        if !tail
+        {
+            self.emit(Op::Unbind);
+        }
+    }
+
+    fn cond(&mut self, clauses: &[(Node, Option<Node>)], malformed: &Option<EvalError>, tail: bool)
+    {
+        // jumps that arrive with the cond's value on the stack
+        let mut ends = Vec::new();
+
+        for (test, body) in clauses
+        {
+            self.node(test, false);
+
+            match *body
+            {
+                Some(ref body) =>
+                {
+                    let next = self.emit(Op::JumpIfList(0));
+
+                    self.node(body, tail);
+
+                    if !tail
+                    {
+                        ends.push(self.emit(Op::Jump(0)));
+                    }
+
+                    self.patch(next);
+                },
+                None => ends.push(self.emit(Op::BranchUnlessList(0)))
+            }
+        }
+
+        match *malformed
+        {
+            Some(ref err) => self.raise(err.clone()),
+            None =>
+            {
+                self.constant(Expression::List(List::nil()));
+                self.finish(tail);
+            }
+        }
+
+        self.land(ends, tail);
+    }
+
+    /// Evaluates `parts` in order for the value of the last one, leaving early at a value `branch` jumps on
+    fn sequence(&mut self, parts: &[Node], empty: Expression, branch: Option<Op>, tail: bool)
+    {
+        let (last, init) = match parts.split_last()
+        {
+            Some(split) => split,
+            None =>
+            {
+                self.constant(empty);
+                return self.finish(tail);
+            }
+        };
+
+        let mut ends = Vec::new();
+
+        for part in init
+        {
+            self.node(part, false);
+
+            match branch
+            {
+                Some(branch) => ends.push(self.emit(branch)),
+                None =>
+                {
+                    self.emit(Op::Pop);
+                }
+            }
+        }
+
+        self.node(last, tail);
+        self.land(ends, tail);
+    }
+
+    /// Points jumps that carry a value to here, where it's returned in tail position
+    fn land(&mut self, ends: Vec<usize>, tail: bool)
+    {
+        if ends.is_empty()
+        {
+            return;
+        }
+
+        for end in ends
+        {
+            self.patch(end);
+        }
+
+        self.finish(tail);
+    }
+
+    fn call(&mut self, node: &Node, function: &Node, arguments: &[Node], tail: bool)
+    {
+        let count = arguments.len();
+        let call = if tail { Op::TailCall(count) } else { Op::Call(count) };
+
+        self.node(function, false);
+
+        let prepare = self.emit(Op::Prepare(count, 0));
+
+        for arg in arguments
+        {
+            self.node(arg, false);
+        }
+
+        self.emit(call);
+
+        let end = if tail { None } else { Some(self.emit(Op::Jump(0))) };
+
+        // macros and special forms are only known at runtime, and take their arguments as written
+        self.patch(prepare);
+
+        for arg in node.raw_arguments()
+        {
+            self.constant(arg.clone());
+        }
+
+        self.emit(call);
+
+        if let Some(end) = end
+        {
+            self.patch(end);
+        }
+    }
+}
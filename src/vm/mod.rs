@@ -0,0 +1,341 @@
+//! A bytecode backend, which compiles expressions to a compact list of ops for a stack machine
+//!
+//! The tree-walker in `eval` is the reference implementation; this backend has to agree with it on
+//! every result and error, which the differential tests check
+
+mod code;
+mod compiler;
+
+#[cfg(test)]
+mod test;
+
+pub use self::code::Code;
+
+use std::rc::Rc;
+
+use expression::*;
+use error::{EvalError, ErrorKind};
+use eval::{append, truthy, Environment};
+use analysis::{analyze, Address};
+use expand::{expand, macroexpand, Macro};
+
+use self::code::Op;
+use self::compiler::compile;
+
+pub fn eval_in(input: Expression, env: &mut Environment) -> Result<Expression, EvalError>
+{
+    let input = expand(&input, env)?;
+    let code = Rc::new(compile(&analyze(&input)));
+
+    Machine
+    {
+        env,
+        stack: Vec::new(),
+        frames: vec![Frame
+        {
+            code,
+            pc: 0,
+            base: 0,
+            scope: Scope::new(),
+            callee: None,
+        }],
+    }.run()
+}
+
+struct Frame
+{
+    code: Rc<Code>,
+    pc: usize,
+    /// Where this frame's temporaries start on the stack
+    base: usize,
+    scope: Scope,
+    /// The closure this frame is a call to, and the atom it was called through, for backtraces
+    callee: Option<(Closure, Option<Atom>)>,
+}
+
+struct Machine<'a>
+{
+    env: &'a mut Environment,
+    stack: Vec<Expression>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Machine<'a>
+{
+    fn run(mut self) -> Result<Expression, EvalError>
+    {
+        loop
+        {
+            match self.step()
+            {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {},
+                Err(err) => return Err(self.unwind(err))
+            }
+        }
+    }
+
+    /// Adds the failing form and the active functions to an error, like the tree-walker does as it returns
+    fn unwind(&self, err: EvalError) -> EvalError
+    {
+        let mut err = match self.frames.last()
+        {
+            Some(frame) => err.in_form(frame.code.form(frame.pc - 1)),
+            None => err
+        };
+
+        for frame in self.frames.iter().rev()
+        {
+            if let Some((ref func, called_as)) = frame.callee
+            {
+                err = err.called_from(func.call_name(called_as));
+            }
+        }
+
+        err
+    }
+
+    fn current(&self) -> &Frame
+    {
+        self.frames.last().expect("the machine always has a frame while it's running")
+    }
+
+    fn frame(&mut self) -> &mut Frame
+    {
+        self.frames.last_mut().expect("the machine always has a frame while it's running")
+    }
+
+    fn pop(&mut self) -> Expression
+    {
+        self.stack.pop().expect("the compiler only pops values it has pushed")
+    }
+
+    /// Runs one op, returning the result of the whole program once the outermost frame returns
+    fn step(&mut self) -> Result<Option<Expression>, EvalError>
+    {
+        // the op is copied out so the frame's code is only borrowed again by the ops that need it
+        let (op, pc) =
+        {
+            let frame = self.frame();
+            frame.pc += 1;
+            (frame.code.ops[frame.pc - 1], frame.pc - 1)
+        };
+
+        match op
+        {
+            Op::Const(index) =>
+            {
+                let value = self.current().code.constants[index].clone();
+                self.stack.push(value);
+            },
+            Op::Local(atom, address) =>
+            {
+                // only a `letrec` variable used before it's value is ready is missing
+                let value = self.current().scope.get(address).ok_or_else(|| EvalError::unbound(atom))?;
+                self.stack.push(value);
+            },
+            Op::Global(name) =>
+            {
+                let value = self.env.global(name.symbol()).cloned().ok_or_else(|| EvalError::unbound(name))?;
+                self.stack.push(value);
+            },
+            Op::Lambda(index) =>
+            {
+                let frame = self.current();
+                let closure = Closure::new(frame.code.lambdas[index].clone(), frame.scope.clone());
+                self.stack.push(Expression::Closure(closure));
+            },
+            Op::Label(name) => match self.pop()
+            {
+                Expression::Closure(func) => self.stack.push(Expression::Closure(func.labelled(name))),
+                other => return Err(EvalError::type_error("label", "a lambda or a macro", other))
+            },
+            Op::Define(name) =>
+            {
+                let value = self.pop();
+                self.env.set_global(name.symbol(), value);
+                self.stack.push(Expression::Atom(name));
+            },
+            Op::Defmacro(name, index) =>
+            {
+                let frame = self.current();
+                let expander = Closure::new(frame.code.lambdas[index].clone(), frame.scope.clone());
+                self.env.set_macro(name.symbol(), Macro::Procedure(expander));
+                self.stack.push(Expression::Atom(name));
+            },
+            Op::Macroexpand(all) =>
+            {
+                let form = self.pop();
+                let expansion = macroexpand(form, all, self.env)?;
+                self.stack.push(expansion);
+            },
+            Op::Bind(count) =>
+            {
+                let values = self.stack.split_off(self.stack.len() - count);
+                let frame = self.frame();
+                frame.scope = frame.scope.push(values);
+            },
+            Op::Reserve(count) =>
+            {
+                let frame = self.frame();
+                frame.scope = frame.scope.reserve(count);
+            },
+            Op::Store(index) =>
+            {
+                let value = self.pop();
+                self.current().scope.set(index, value);
+            },
+            Op::Unbind =>
+            {
+                let frame = self.frame();
+                frame.scope = frame.scope.parent();
+            },
+            Op::JumpIfBound(index, target) => if self.current().scope.get(Address { depth: 0, index }).is_some()
+            {
+                self.frame().pc = target;
+            },
+            Op::Jump(target) => self.frame().pc = target,
+            Op::JumpIfList(target) => if !truthy(&self.pop())
+            {
+                self.frame().pc = target;
+            },
+            Op::BranchIfList(target) => self.branch(target, false),
+            Op::BranchUnlessList(target) => self.branch(target, true),
+            Op::Pop =>
+            {
+                self.pop();
+            },
+            Op::Cons =>
+            {
+                let cdr = self.pop();
+                let car = self.pop();
+                self.stack.push(Expression::List(List::cons(car, cdr)));
+            },
+            Op::Append =>
+            {
+                let rest = self.pop();
+                let list = self.pop();
+                self.stack.push(append(list, rest)?);
+            },
+            Op::Prepare(count, raw) =>
+            {
+                let operative = match self.stack.last()
+                {
+                    Some(Expression::Closure(func)) =>
+                    {
+                        if !func.arity().accepts(count)
+                        {
+                            return Err(EvalError::arity(func.call_name(called_as(self.current().code.form(pc))), func.arity(), count));
+                        }
+
+                        func.kind() == ClosureKind::Macro
+                    },
+                    Some(Expression::Native(func)) => func.kind() == NativeKind::SpecialForm,
+                    _ => false
+                };
+
+                if operative
+                {
+                    self.frame().pc = raw;
+                }
+            },
+            Op::Call(count) => return self.call(pc, count, false),
+            Op::TailCall(count) => return self.call(pc, count, true),
+            Op::Return => return Ok(self.ret()),
+            Op::Raise(index) => return Err(self.current().code.errors[index].clone())
+        }
+
+        Ok(None)
+    }
+
+    /// Keeps the value on top of the stack and jumps if it's truthiness is `when`, and pops it otherwise
+    fn branch(&mut self, target: usize, when: bool)
+    {
+        let value = self.stack.last().expect("the compiler only branches on values it has pushed");
+
+        if truthy(value) == when
+        {
+            self.frame().pc = target;
+        }
+        else
+        {
+            self.pop();
+        }
+    }
+
+    fn call(&mut self, pc: usize, count: usize, tail: bool) -> Result<Option<Expression>, EvalError>
+    {
+        let arguments = self.stack.split_off(self.stack.len() - count);
+
+        match self.pop()
+        {
+            Expression::Closure(func) =>
+            {
+                let called_as = called_as(self.current().code.form(pc));
+                let lambda = func.lambda();
+                let body = lambda.code.get_or_init(|| Rc::new(compile(&lambda.body))).clone();
+                let scope = func.frame(called_as, arguments)?;
+
+                if tail
+                {
+                    let frame = self.frames.pop().expect("the machine always has a frame while it's running");
+                    self.stack.truncate(frame.base);
+                }
+
+                let base = self.stack.len();
+
+                self.frames.push(Frame
+                {
+                    code: body,
+                    pc: 0,
+                    base,
+                    scope,
+                    callee: Some((func, called_as)),
+                });
+
+                Ok(None)
+            },
+            Expression::Native(func) =>
+            {
+                let value = func.call(&arguments).map_err(|err| err.called_from(func.name()))?;
+
+                self.stack.push(value);
+
+                Ok(if tail { self.ret() } else { None })
+            },
+            other => Err(EvalError::new(ErrorKind::NotCallable(other)))
+        }
+    }
+
+    fn ret(&mut self) -> Option<Expression>
+    {
+        let value = self.pop();
+        let frame = self.frames.pop().expect("the machine always has a frame while it's running");
+
+        self.stack.truncate(frame.base);
+
+        if self.frames.is_empty()
+        {
+            Some(value)
+        }
+        else
+        {
+            self.stack.push(value);
+            None
+        }
+    }
+}
+
+/// The atom a call names it's function with, if it does
+fn called_as(call: &Expression) -> Option<Atom>
+{
+    match *call
+    {
+        Expression::List(ref list) => match list.car()
+        {
+            Some(&Expression::Atom(name)) => Some(name),
+            _ => None
+        },
+        _ => None
+    }
+}
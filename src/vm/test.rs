@@ -0,0 +1,459 @@
+use interpreter::{Interpreter, Backend};
+use expression::*;
+use error::{EvalError, ErrorKind};
+use token::Token;
+
+#[derive(Debug, PartialEq)]
+enum Outcome
+{
+    Value(String),
+    Error(ErrorKind, Option<String>, Vec<String>),
+}
+
+fn outcome(result: Result<Expression, EvalError>) -> Outcome
+{
+    match result
+    {
+        Ok(value) => Outcome::Value(format!("{}", value)),
+        Err(err) => Outcome::Error(err.kind().clone(), err.form().map(|form| format!("{}", form)), err.backtrace().to_vec())
+    }
+}
+
+fn run(backend: Backend, input: &str) -> Vec<Outcome>
+{
+    let mut interpreter = Interpreter::with_backend(backend);
+
+    interpreter.register_special_form("quoted-length", 1, |args| Ok(Expression::Number(Number::Int(match args[0]
+    {
+        Expression::List(ref list) => list.len() as i64,
+        _ => -1
+    }))));
+
+    Expression::parse_all(Token::lex(input).unwrap()).unwrap()
+        .into_iter()
+        .map(|expr| outcome(interpreter.eval(expr)))
+        .collect()
+}
+
+/// Runs `input` through both backends, checking they agree on every result and error
+fn differential(input: &str) -> Vec<Outcome>
+{
+    let expected = run(Backend::TreeWalker, input);
+    let actual = run(Backend::Bytecode, input);
+
+    assert_eq!(expected, actual, "the backends disagree on:\n{}", input);
+
+    actual
+}
+
+fn values(input: &str) -> Vec<String>
+{
+    differential(input).into_iter()
+        .map(|outcome| match outcome
+        {
+            Outcome::Value(value) => value,
+            Outcome::Error(kind, ..) => panic!("unexpected error: {}", kind)
+        })
+        .collect()
+}
+
+#[test]
+fn primitives()
+{
+    assert_eq!(vec!["a", "(a b)", "t", "()", "a", "(b c)", "(a b c)", "(a . b)", "3", "\"s\""], values("'a
+        '(a b)
+        (atom 'a)
+        (eq 'a 'b)
+        (car '(a b c))
+        (cdr '(a b c))
+        (cons 'a '(b c))
+        (cons 'a 'b)
+        (+ 1 2)
+        \"s\""));
+}
+
+#[test]
+fn cond()
+{
+    assert_eq!(vec!["b", "a", "(c)", "x"], values("(cond ('() 'a) ('a 'b))
+        (cond ('t 'a) ('() 'b))
+        (cons (cond ('() 'a) ('t 'c)) '())
+        (cond ('t 'x) (malformed))"));
+}
+
+#[test]
+fn functions()
+{
+    assert_eq!(vec!["#<lambda (x)>", "(a b c)", "((quote a) b c)", "b", "x", "first", "a", "adder", "7", "(x . y)", "3"], values("(lambda (x) (cons x '(b c)))
+        ((lambda (x) (cons x '(b c))) 'a)
+        ((macro (x) (cons x '(b c))) 'a)
+        ((label f (lambda (x) (cond ((atom x) (f '())) ('t 'b)))) 'a)
+        (define x 'x)
+        (defun first (l) (car l))
+        (first '(a b))
+        (defun adder (n) (lambda (x) (+ x n)))
+        ((adder 3) 4)
+        (((lambda (a) (lambda (b) (cons a b))) 'x) 'y)
+        (quoted-length (a b c))"));
+}
+
+#[test]
+fn recursion()
+{
+    assert_eq!(vec!["map", "scale", "(3 6 9)", "count", "build", "3000", "500"], values("(defun map (f l) (cond ((eq l '()) '()) ('t (cons (f (car l)) (map f (cdr l))))))
+        (defun scale (n l) (map (lambda (x) (* x n)) l))
+        (scale 3 '(1 2 3))
+        (defun count (n acc) (cond ((= n 0) acc) ('t (count (- n 1) (+ acc 1)))))
+        (defun build (n acc) (cond ((= n 0) acc) ('t (build (- n 1) (cons n acc)))))
+        (count 3000 0)
+        ((label walk (lambda (l n) (cond ((eq l '()) n) ('t (walk (cdr l) (+ n 1)))))) (build 500 '()) 0)"));
+}
+
+#[test]
+fn shadowing()
+{
+    assert_eq!(vec!["f", "(b a)", "x", "(outer . inner)", "((x y) (x))"], values("(defun f (x x) x)
+        (cons (f 'a 'b) (cons ((label x (lambda (x) x)) 'a) '()))
+        (define x 'inner)
+        ((lambda (y) ((lambda (x) (cons y x)) x)) 'outer)
+        ((label g (lambda (g) (cons (cons 'x g) (cons (cons 'x '()) '())))) '(y))"));
+}
+
+#[test]
+fn sequencing()
+{
+    assert_eq!(vec!["()", "()", "b", "(a)", "()", "a", "t", "()", "b", "()", "()", "t", "c", "c", "f", "(())", "done"], values("(cond ('() 'a))
+        (cond)
+        (cond ('() 'a) ('b))
+        (cons (cond ('() 'a) ('t 'b 'a)) '())
+        (if '() 'a)
+        (if 't 'a 'b)
+        (and)
+        (and 'a '() 'b)
+        (and 'a 'b)
+        (or)
+        (or '() '())
+        (or '() 't 'b)
+        (progn 'a 'b 'c)
+        (begin (car '(a)) 'c)
+        (defun f (n) (when (= n 0) (cons 3 2)) (unless (= n 0) (f (- n 1))))
+        (cons (f 2000) (when (f 0) (cons 3 2)))
+        ((lambda () (not 'a) (or (and (not '()) 'done) 'no)))"));
+}
+
+#[test]
+fn constants()
+{
+    assert_eq!(vec!["t", "()", "()", ":key", "(t () :a)", "t", "(t)"], values("t
+        nil
+        ()
+        :key
+        (cons t (cons nil (cons :a ())))
+        (eq nil '())
+        ((lambda (x) (cons t x)) nil)"));
+}
+
+#[test]
+fn quasiquotes()
+{
+    assert_eq!(vec!["x", "(a b)", "(a x c)", "(a 1 2 c)", "(a 1 2)", "(1 2 . x)", "(a . x)", "(a (quasiquote (b (unquote (c x)))))", "(quasiquote (unquote 1 2))", "(1 2 3)", "(a ())", "m", "(e (quote e) . 3)"], values("(define x 'x)
+        `(a b)
+        `(a ,x c)
+        (let ((y '(1 2))) `(a ,@y c))
+        (let ((y '(1 2))) `(a ,@y))
+        (let ((y '(1 2))) `(,@y . ,x))
+        `(a . ,x)
+        `(a `(b ,(c ,x)))
+        (let ((y '(1 2))) ``,,@y)
+        (let ((y '(2 3))) `(1 ,@y ,@()))
+        `(a ,@'() ,nil)
+        (define m 'm)
+        ((macro (v) `(,v ',v . ,(+ 1 2))) e)"));
+
+    let outcomes = differential("`,@x
+        ,x
+        (let ((y 'a)) `(,@y))
+        `(a ,(car 'b))");
+
+    assert!(outcomes.iter().all(|outcome| matches!(outcome, Outcome::Error(..))));
+}
+
+#[test]
+fn macros()
+{
+    assert_eq!(vec!["my-if", "yes", "no", "unless-nil", "(cond ((quote ()) (quote yes)) (t (quote no)))", "(my-if (quote ()) (quote yes) (quote no))", "yes", "(a b)", "3", "twice", "(cons (cons 1 ()) (cons 1 ()))", "(b . a)"], values("(defmacro my-if (test then else) `(cond (,test ,then) (t ,else)))
+        (my-if 't 'yes 'no)
+        (my-if '() 'yes 'no)
+        (defmacro unless-nil (test then else) `(my-if ,test ,else ,then))
+        (macroexpand '(unless-nil '() 'no 'yes))
+        (macroexpand-1 '(unless-nil '() 'no 'yes))
+        (let ((x 't)) (unless-nil x 'no 'yes))
+        (my-if 't '(a b) (car 'never))
+        (let ((n 1)) (cond ((my-if 't 't 't) (+ n 2))))
+        (defmacro twice (x) `(cons ,x ,x))
+        (macroexpand '(twice (cons 1 ())))
+        ((lambda (a b) `(,(my-if 't b a) . ,a)) 'a 'b)"));
+
+    assert_eq!(vec!["my-if", "unless-nil", "(cond (a b) (t c))", "(quote (unless-nil x y z))", "(quote (a b))"], values("(defmacro my-if (test then else) `(cond (,test ,then) (t ,else)))
+        (defmacro unless-nil (test then else) `(my-if ,test ,else ,then))
+        (macroexpand-1 '(my-if a b c))
+        ''(unless-nil x y z)
+        `',(my-if 't '(a b) 'c)"));
+
+    let outcomes = differential("(defmacro broken (x) (car x))
+        (broken y)
+        (defmacro pair (a b) `(cons ,a ,b))
+        (pair 1)
+        (pair 1 unbound)
+        (defmacro inf () '(inf))
+        (inf)
+        (macroexpand '(inf))
+        (defmacro deeper () '(cons 1 (deeper)))
+        (deeper)");
+
+    assert!(matches!(outcomes[1], Outcome::Error(ErrorKind::Type { .. }, Some(_), ref backtrace) if backtrace == &["car", "broken"]));
+    assert!(matches!(outcomes[3], Outcome::Error(ErrorKind::Arity { .. }, Some(ref form), _) if form == "(pair 1)"));
+    assert!(matches!(outcomes[4], Outcome::Error(ErrorKind::UnboundAtom(_), ..)));
+
+    for outcome in &[&outcomes[6], &outcomes[7]]
+    {
+        assert!(matches!(**outcome, Outcome::Error(ErrorKind::ExpansionTooDeep(ref call), Some(ref form), _) if call.to_string() == "(inf)" && form == "(inf)"));
+    }
+
+    assert!(matches!(outcomes[9], Outcome::Error(ErrorKind::ExpansionTooDeep(_), Some(ref form), _) if form == "(deeper)"));
+}
+
+#[test]
+fn syntax_rules()
+{
+    assert_eq!(vec!["my-or", "5", "()", "b", "first", "1", "pairs", "((a . 1) (b . 2))", "()", "arrow", "(1 . 2)", "(no)", "rest", "(c d)", "sym", "t", "(let ((t0 a)) (if t0 t0 (my-or b)))"], values("(define-syntax my-or
+            (syntax-rules ()
+                ((_) '())
+                ((_ e) e)
+                ((_ e r ...) (let ((t0 e)) (if t0 t0 (my-or r ...))))))
+        (let ((t0 5)) (my-or '() t0))
+        (my-or)
+        (my-or '() '() 'b)
+        (define-syntax first (syntax-rules () ((_ l) (car l))))
+        (let ((car cdr)) (first '(1 2)))
+        (define-syntax pairs (syntax-rules () ((_ (a b) ...) `(,(cons 'a b) ...))))
+        (pairs (a 1) (b 2))
+        (pairs)
+        (define-syntax arrow (syntax-rules (=>) ((_ a => b) (cons a b)) ((_ a b) '(no))))
+        (arrow 1 => 2)
+        (arrow 1 2)
+        (define-syntax rest (syntax-rules () ((_ a . more) 'more)))
+        (rest b c d)
+        (define-syntax sym (syntax-rules () ((_) 'foo)))
+        (eq (sym) 'foo)
+        (macroexpand-1 '(my-or a b))"));
+
+    assert_eq!(vec!["()", "t", "g"], values("(eq (gensym) (gensym))
+        (let ((g (gensym \"x\"))) (eq g g))
+        (let ((g (gensym))) (cond ((eq g 'g1) 'collided) ('t 'g)))"));
+
+    let outcomes = differential("(define-syntax two (syntax-rules () ((_ a b) (cons a b))))
+        (two 1)
+        (define-syntax bad (syntax-rules () ((_ a ...) a)))
+        (bad 1 2)
+        (define-syntax worse (syntax-rules () ((_ ... a) a)))
+        (define-syntax inf (syntax-rules () ((_) (inf))))
+        (inf)
+        (macroexpand '(inf))
+        (define-syntax deeper (syntax-rules () ((_ x) (cons x (deeper x)))))
+        (deeper 1)");
+
+    assert!(matches!(outcomes[1], Outcome::Error(ErrorKind::Syntax(_), Some(ref form), _) if form == "(two 1)"));
+    assert!(matches!(outcomes[3], Outcome::Error(ErrorKind::Syntax(_), ..)));
+    assert!(matches!(outcomes[4], Outcome::Error(ErrorKind::Syntax(_), ..)));
+
+    for outcome in &[&outcomes[6], &outcomes[7]]
+    {
+        assert!(matches!(**outcome, Outcome::Error(ErrorKind::ExpansionTooDeep(ref call), Some(ref form), _) if call.to_string() == "(inf)" && form == "(inf)"));
+    }
+
+    assert!(matches!(outcomes[9], Outcome::Error(ErrorKind::ExpansionTooDeep(ref call), ..) if call.to_string() == "(deeper 1)"));
+}
+
+#[test]
+fn parameters()
+{
+    assert_eq!(vec!["list", "(1 2 3)", "()", "(1 2 3)", "(1 2)",
+        "greet", "(\"hi\" bob)", "(\"yo\" bob . !)",
+        "box", "(1 1 ())", "(1 3 ())", "(5 5 2)",
+        "kw", "(1 :a 1)", "a", "my-when", "2", "count", "5000",
+        "#<lambda (a &optional b &rest c &key d)>", "#<macro f (x &rest y)>",
+        "two", "f", "(2 . 2)", "(1 . 2)", "2", "keyed", "(1 . 2)"], values("(defun list (&rest xs) xs)
+        (list 1 2 3)
+        (list)
+        ((lambda (a . rest) (cons a rest)) 1 2 3)
+        ((lambda args args) 1 2)
+        (defun greet (name &optional (greeting \"hi\") punct) (cons greeting (cons name punct)))
+        (greet 'bob)
+        (greet 'bob \"yo\" '!)
+        (defun box (&key (width 1) (height width) depth) `(,width ,height ,depth))
+        (box)
+        (box :height 3)
+        (box :depth 2 :width 5 :width 6)
+        (defun kw (&rest all &key a) (cons a all))
+        (kw :a 1)
+        ((macro (&rest forms) (car forms)) a b)
+        (defmacro my-when (test &rest body) `(if ,test (progn ,@body)))
+        (my-when 't 1 2)
+        (defun count (n &optional (acc 0)) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+        (count 5000)
+        (lambda (a &optional b &rest c &key d) a)
+        (label f (macro (x . y) x))
+        (defmacro two () 2)
+        (defun f (&optional (a (two)) &key (b (two))) (cons a b))
+        (f)
+        (f 1)
+        ((lambda (&optional (a (two))) a))
+        (define-syntax keyed (syntax-rules () ((_ x) (lambda (&key a b) (cons a x)))))
+        ((keyed 2) :b 3 :a 1)"));
+
+    let outcomes = differential("(defun greet (name &optional greeting) name)
+        (greet)
+        (greet 1 2 3)
+        (defun box (&key width) width)
+        (box :size 1)
+        (box :width)
+        (lambda (&rest) x)
+        (lambda (&key a &optional b) a)
+        (lambda (a . t) a)
+        (lambda (&optional (a 1 2)) a)");
+
+    assert!(matches!(outcomes[1], Outcome::Error(ErrorKind::Arity { expected: Arity::Between(1, 2), found: 0, .. }, ..)));
+    assert!(matches!(outcomes[2], Outcome::Error(ErrorKind::Arity { .. }, ..)));
+    assert!(matches!(outcomes[4], Outcome::Error(ErrorKind::Type { ref expected, .. }, Some(ref form), _) if expected == "one of the keywords :width" && form == "(box :size 1)"));
+    assert!(matches!(outcomes[5], Outcome::Error(ErrorKind::Type { .. }, ..)));
+    assert!(matches!(outcomes[6], Outcome::Error(ErrorKind::Syntax(_), ..)));
+    assert!(matches!(outcomes[7], Outcome::Error(ErrorKind::Syntax(_), ..)));
+    assert!(matches!(outcomes[8], Outcome::Error(ErrorKind::Type { .. }, ..)));
+    assert!(matches!(outcomes[9], Outcome::Error(ErrorKind::Type { .. }, ..)));
+}
+
+#[test]
+fn equality()
+{
+    assert_eq!(vec!["t", "()", "t", "()", "t", "()", "t", "()", "t", "()", "t", "t", "t",
+        "member", "((c) d)", "()", "assoc", "((b) . 2)", "()"], values("(equal '(a (b \"c\") . d) '(a (b \"c\") . d))
+        (equal '(a b) '(a b c))
+        (equal 1 1)
+        (equal 1 1.0)
+        (equal \"s\" \"s\")
+        (eqv \"s\" \"s\")
+        (eqv car car)
+        (eqv '(a) '(a))
+        (eqv 2.5 2.5)
+        (eq (lambda (x) x) (lambda (x) x))
+        (let ((x '(a))) (eqv x x))
+        (let ((f (lambda (x) x))) (eqv f f))
+        (eqv '() '())
+        (defun member (x l) (cond ((eq l '()) '()) ((equal x (car l)) l) ('t (member x (cdr l)))))
+        (member '(c) '(a b (c) d))
+        (member 'e '(a b))
+        (defun assoc (key alist) (cond ((eq alist '()) '()) ((equal key (car (car alist))) (car alist)) ('t (assoc key (cdr alist)))))
+        (assoc '(b) '((a . 1) ((b) . 2)))
+        (assoc 'c '((a . 1)))"));
+}
+
+#[test]
+fn bindings()
+{
+    assert_eq!(vec!["x", "(1 . x)", "(1 . 1)", "(t)", "f", "1", "((2 . 1))"], values("(define x 'x)
+        (let ((x 1) (y x)) (cons x y))
+        (let* ((x 1) (y x)) (cons x y))
+        (letrec ((even (lambda (n) (cond ((= n 0) 't) ('t (odd (- n 1))))))
+                 (odd (lambda (n) (cond ((= n 0) '()) ('t (even (- n 1)))))))
+            (cons (even 10) (odd 10)))
+        (defun f (n) (let ((m (+ n 1))) (cond ((= n 0) m) ('t (f (- n 1))))))
+        (f 2000)
+        (cons (let ((a 1) (b 2)) ((lambda () (cons b a)))) '())"));
+}
+
+#[test]
+fn letrec_frames()
+{
+    use std::rc::Rc;
+
+    // `probe` returns a fresh native each call which holds a reference to `token`, so the count
+    // shows how many of them, and so of the frames binding them, are still alive
+    let token = Rc::new(());
+
+    for &backend in &[Backend::TreeWalker, Backend::Bytecode]
+    {
+        let mut interpreter = Interpreter::with_backend(backend);
+        let held = token.clone();
+
+        interpreter.register_fn("probe", 0, move |_| {
+            let held = held.clone();
+            Ok(Expression::Native(Native::new("held", Arity::Exact(0), NativeKind::Function, move |_| {
+                let _ = &held;
+                Ok(Expression::Number(Number::Int(1)))
+            })))
+        });
+
+        let eval = |interpreter: &mut Interpreter, input: &str| interpreter.eval_str(input).unwrap().pop().unwrap().to_string();
+
+        for _ in 0..3
+        {
+            assert_eq!("1", eval(&mut interpreter, "(letrec ((p (probe)) (f (lambda (x) (p)))) (f 1))"));
+            assert_eq!("1", eval(&mut interpreter, "(defun g () (letrec ((p (probe)) (f (lambda () (p)))) (f))) (g)"));
+        }
+
+        assert_eq!(2, Rc::strong_count(&token));
+
+        // a recursive function that escapes it's `letrec` keeps the frame alive while it's reachable
+        assert_eq!("(1 . done)", eval(&mut interpreter, "(define h (letrec ((p (probe)) (f (lambda (n) (cond ((= n 0) (cons (p) 'done)) ('t (f (- n 1))))))) f)) (h 3)"));
+        assert_eq!(3, Rc::strong_count(&token));
+
+        eval(&mut interpreter, "(define h '())");
+        assert_eq!(2, Rc::strong_count(&token));
+    }
+
+    assert_eq!(1, Rc::strong_count(&token));
+}
+
+#[test]
+fn errors()
+{
+    let outcomes = differential("(defun f (x) (car x))
+        (defun g (x) (cons (f x) '()))
+        (g 'a)
+        (f y)
+        (cond ('() 'a) b)
+        (if 'a)
+        (cond 'a)
+        (1 2)
+        (f 'a 'b)
+        (t 'a)
+        (define t '())
+        ((lambda (:key) :key) 'a)
+        (let ((nil 'a)) nil)
+        (a . b)
+        (quote a b)
+        (lambda (1) x)
+        (label 1 x)
+        (label x 'a)
+        (define 1 x)
+        (+ 'a 1)
+        ((macro (x y) x) 'a)
+        ((lambda (x) (undefined x)) 'a)
+        (let ((x)) x)
+        (let x x)
+        (letrec ((a b) (b 'b)) a)
+        (let ((x (car 'a))) x)");
+
+    assert!(outcomes[2..].iter().all(|outcome| matches!(outcome, Outcome::Error(..))));
+
+    match outcomes[2]
+    {
+        Outcome::Error(ErrorKind::Type { .. }, Some(ref form), ref backtrace) =>
+        {
+            assert_eq!("(car x)", form);
+            assert_eq!(&["car".to_owned(), "f".to_owned(), "g".to_owned()], &backtrace[..]);
+        },
+        ref other => panic!("expected a type error, got {:?}", other)
+    }
+}